@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use clap::{Parser, ValueEnum};
 
 #[derive(Parser)]
@@ -8,9 +10,73 @@ pub struct Cli {
     pub debug: bool,
     #[arg(short, long, value_enum)]
     pub interface: Option<InterfaceMode>,
+    /// Screen width (in characters) of the dumb interface.
+    #[arg(long, default_value_t = 80)]
+    pub width: u16,
+    /// Screen height (in lines) of the dumb interface.
+    #[arg(long, default_value_t = 24)]
+    pub height: u16,
+    /// How the dumb interface shows the upper window.
+    #[arg(long, value_enum, default_value_t = UpperWindowMode::Grid)]
+    pub upper_window: UpperWindowMode,
+    /// Read commands from a file before reading from the keyboard.
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+    /// Record every command and keypress to a file (output stream 4), for use with --replay.
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+    /// A Blorb file holding the story's pictures, if they aren't in the story file.
+    #[arg(long, value_name = "FILE")]
+    pub blorb: Option<PathBuf>,
+    /// Write each frame of a version 6 story's screen to a directory, as PNG files.
+    #[arg(long, value_name = "DIR")]
+    pub frames: Option<PathBuf>,
+    /// Keep the story's auxiliary files (such as high score tables) in this directory.
+    #[arg(long, value_name = "DIR")]
+    pub aux_dir: Option<PathBuf>,
+    /// The number of moves that can be undone. 0 turns undo off.
+    #[arg(long, value_name = "MOVES", default_value_t = 100)]
+    pub undo_depth: usize,
+    /// The prefix for interpreter commands (such as /undo and /help) typed at the game's prompt.
+    /// Choose another if the game uses it, or an empty one to turn interpreter commands off.
+    #[arg(long, value_name = "PREFIX", default_value = "/")]
+    pub command_prefix: String,
+    /// How to play the sound effects in the story's Blorb file.
+    #[arg(long, value_enum, default_value_t = SoundMode::Player)]
+    pub sound: SoundMode,
+    /// The program to play sounds with. `{file}` is replaced with the sound file to play, and
+    /// `{volume}` with the volume (from 0 to 100).
+    #[arg(
+        long,
+        value_name = "COMMAND",
+        default_value = "ffplay -nodisp -autoexit -loglevel quiet -volume {volume} {file}"
+    )]
+    pub sound_player: String,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum InterfaceMode {
     Terminal,
+    /// Plain text on stdin and stdout, with no terminal required.
+    Dumb,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+pub enum UpperWindowMode {
+    /// Discard everything printed to the upper window.
+    Hide,
+    /// Print upper window text as it arrives, as though it were in the lower window.
+    Inline,
+    /// Draw the upper window as a grid of text whenever it changes, before reading input.
+    Grid,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+pub enum SoundMode {
+    /// Tell the story that sound effects aren't available.
+    Off,
+    /// Play nothing, but log each sound effect.
+    Log,
+    /// Play sound effects with an external program (see --sound-player), if it can be found.
+    Player,
 }
@@ -1,12 +1,22 @@
 mod address;
 mod alphabet;
+mod auxiliary;
+mod command_file;
 pub mod error;
+mod graphics;
 pub mod input_code;
 mod instruction;
 mod memory;
+mod meta_command;
+pub mod options;
 mod property;
+mod quetzal;
+pub mod sound;
 mod stack;
 pub mod state;
+mod transcript;
+mod undo;
 pub use input_code::InputCode;
+pub use options::Options;
 
 pub type Result<T> = std::result::Result<T, error::GameError>;
@@ -4,6 +4,7 @@
 // Common to all versions
 pub const VERSION: usize = 0x0;
 pub const FLAGS_1: usize = 0x1;
+pub const RELEASE_NUMBER: usize = 0x2;
 pub const HIGH_MEMORY_BASE: usize = 0x4;
 pub const PROGRAM_COUNTER_STARTS: usize = 0x6;
 pub const DICTIONARY_LOCATION: usize = 0x8;
@@ -11,9 +12,11 @@ pub const OBJECT_TABLE_LOCATION: usize = 0xA;
 pub const GLOBAL_VARIABLE_TABLE_LOCATION: usize = 0xC;
 pub const STATIC_MEMORY_BASE: usize = 0xE;
 pub const FLAGS_2: usize = 0x10;
+pub const SERIAL_NUMBER: usize = 0x12;
 pub const _STANDARD_REVISION_NUMBER: usize = 0x32;
 
 pub mod flags1_bits_pre_v4 {
+    pub const STATUS_LINE_TYPE: u16 = 1;
     pub const STATUS_LINE_UNAVAILABLE: u16 = 4;
     pub const SCREEN_SPLITTING_AVAILABLE: u16 = 5;
     pub const VARIABLE_PITCH_FONT_DEFAULT: u16 = 6;
@@ -36,7 +39,7 @@ pub mod flags2 {
     pub const PICTURE_SUPPORT: u16 = 3;
     pub const UNDO_SUPPORT: u16 = 4;
     pub const MOUSE_SUPPORT: u16 = 5;
-    pub const COLOR_SUPPORT: u16 = 6;
+    pub const _COLOR_SUPPORT: u16 = 6;
     pub const SOUND_EFFECT_SUPPORT: u16 = 7;
     pub const MENU_SUPPORT: u16 = 8;
 }
@@ -57,19 +60,21 @@ pub const SCREEN_WIDTH_CHARS: usize = 0x21; // ditto
 // Version 5+
 pub const SCREEN_WIDTH_UNITS: usize = 0x22;
 pub const SCREEN_HEIGHT_UNITS: usize = 0x24;
-pub const _FONT_WIDTH: usize = 0x26;
-pub const _FONT_HEIGHT: usize = 0x27;
-pub const _DEFAULT_BACKGROUND_COLOUR: usize = 0x2C;
-pub const _DEFAULT_FOREGROUND_COLOR: usize = 0x2D;
-pub const _TERMINATING_CHARACTER_TABLE_LOCATION: usize = 0x2E;
+pub const FONT_WIDTH: usize = 0x26; // Font height in version 6
+pub const FONT_HEIGHT: usize = 0x27; // Font width in version 6
+pub const ROUTINES_OFFSET: usize = 0x28; // Versions 6 and 7
+pub const STRINGS_OFFSET: usize = 0x2A; // ditto
+pub const DEFAULT_BACKGROUND_COLOUR: usize = 0x2C;
+pub const DEFAULT_FOREGROUND_COLOUR: usize = 0x2D;
+pub const TERMINATING_CHARACTER_TABLE_LOCATION: usize = 0x2E;
 pub const ALPHABET_TABLE_LOCATION: usize = 0x34;
 pub const HEADER_EXTENSION_TABLE_LOCATION: usize = 0x36;
 
 /// Header extension table value offsets
 pub const _EXTENSION_TABLE_REMAINING_WORDS: usize = 0x0;
-pub const _MOUSE_CLICK_COORDS_X: usize = 0x1;
-pub const _MOUSE_CLICK_COORDS_Y: usize = 0x2;
+pub const MOUSE_CLICK_COORDS_X: usize = 0x1;
+pub const MOUSE_CLICK_COORDS_Y: usize = 0x2;
 pub const UNICODE_TRANSLATION_TABLE_LOCATION: usize = 0x3;
 pub const _FLAGS_3: usize = 0x4;
-pub const _TRUE_DEFAULT_FOREGROUND_COLOR: usize = 0x5;
-pub const _TRUE_DEFAULT_BACKGROUND_COLOR: usize = 0x5;
+pub const TRUE_DEFAULT_FOREGROUND_COLOUR: usize = 0x5;
+pub const TRUE_DEFAULT_BACKGROUND_COLOUR: usize = 0x6;
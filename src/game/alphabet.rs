@@ -81,16 +81,20 @@ impl Alphabet {
         })[char as usize - 6]
     }
 
+    /// Find the Z-character for a character, and the alphabet it's in. Returns None if the
+    /// character is in none of them, in which case it must be written as a ZSCII escape.
     pub fn encode_zchar(&self, c: char) -> Option<(u8, AlphabetTable)> {
         if let Some(v) = self.a0.iter().position(|&x| x == c) {
             Some((v as u8 + 6, AlphabetTable::A0))
         } else if let Some(v) = self.a1.iter().position(|&x| x == c) {
             Some((v as u8 + 6, AlphabetTable::A1))
         } else {
+            // The first character of A2 is a placeholder for the ZSCII escape.
             self.a2
                 .iter()
+                .skip(1)
                 .position(|&x| x == c)
-                .map(|v| (v as u8 + 6, AlphabetTable::A2))
+                .map(|v| (v as u8 + 7, AlphabetTable::A2))
         }
     }
 
@@ -102,7 +106,11 @@ impl Alphabet {
             32..=126 => Ok(Some(char::try_from(value as u32).map_err(|_| {
                 GameError::invalid_operation("Could not decode ZSCII character")
             })?)),
-            c @ 155..=251 => Ok(Some(self.unicode_table()[c as usize - 155])),
+            c @ 155..=251 => self
+                .unicode_table()
+                .get(c as usize - 155)
+                .map(|&c| Some(c))
+                .ok_or_else(|| GameError::invalid_operation("ZSCII character missing from table")),
             _ => Err(GameError::invalid_operation("Invalid ZSCII sequence")),
         }
     }
@@ -132,6 +140,11 @@ impl Alphabet {
             CursorDown => Ok(130),
             CursorLeft => Ok(131),
             CursorRight => Ok(132),
+            FunctionKey(n @ 1..=12) => Ok(132 + n),
+            Keypad(n @ 0..=9) => Ok(145 + n),
+            DoubleClick => Ok(253),
+            SingleClick => Ok(254),
+            FunctionKey(_) | Keypad(_) => Err(GameError::invalid_operation("Invalid input key")),
             Character(c) => self.zscii_from_char(c),
         }
     }
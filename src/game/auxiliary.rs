@@ -0,0 +1,73 @@
+//! Auxiliary files, which games use (through the extended forms of `save` and `restore`) to keep
+//! tables of data such as high scores between sessions. Each story keeps its auxiliary files in
+//! a directory of its own, and the names the game or player choose can't reach outside it.
+
+use std::env;
+use std::path::PathBuf;
+
+/// The directory to keep a story's auxiliary files in, when none is given: a directory named
+/// after the story under the user's data directory.
+pub fn default_directory(story_name: &str) -> PathBuf {
+    let data = match env::var_os("XDG_DATA_HOME").filter(|value| !value.is_empty()) {
+        Some(data) => PathBuf::from(data),
+        None => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".local").join("share"),
+            None => PathBuf::from("."),
+        },
+    };
+    let story: String = story_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    data.join("zanthe").join(story)
+}
+
+/// Make a name for an auxiliary file safe to use, following the standard's rules: up to eight
+/// letters and digits, starting with a letter, then optionally a full stop and up to three more.
+/// Other characters are dropped, and the extension defaults to ".aux". Names are case
+/// insensitive, so they're kept in lower case. Returns None if nothing usable is left.
+pub fn file_name(name: &str) -> Option<String> {
+    let (base, extension) = name.rsplit_once('.').unwrap_or((name, ""));
+    let clean = |part: &str, length: usize| -> String {
+        part.chars()
+            .filter(char::is_ascii_alphanumeric)
+            .take(length)
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    };
+    let base = clean(base, 8);
+    let extension = match clean(extension, 3) {
+        extension if extension.is_empty() => "aux".to_string(),
+        extension => extension,
+    };
+    match base.chars().next() {
+        Some(first) if first.is_ascii_alphabetic() => Some(format!("{}.{}", base, extension)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_cleaned_up() {
+        for (name, expected) in [
+            ("SCORES", "scores.aux"),
+            ("Hi-Score.DAT", "hiscore.dat"),
+            ("verylongname.text", "verylong.tex"),
+            ("a.b.c", "ab.c"),
+            ("tables.", "tables.aux"),
+            ("map.!!", "map.aux"),
+        ] {
+            assert_eq!(file_name(name).as_deref(), Some(expected), "{}", name);
+        }
+    }
+
+    #[test]
+    fn names_must_start_with_a_letter() {
+        for name in ["", ".aux", "1scores", "_2nd", "../../.profile"] {
+            assert_eq!(file_name(name), None, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Command files, which hold a recording of the player's input.
+//!
+//! Each line of the file holds one input event. Lines typed by the player are stored as they
+//! were typed. Single keypresses are stored as the key itself, unless the key is not a
+//! printable ASCII character (or is `[`), in which case it is stored as its ZSCII code in
+//! square brackets, such as `[13]`.
+
+use std::fs::File;
+use std::io::{self, prelude::*, BufReader, BufWriter, Lines};
+use std::path::Path;
+
+/// A single keypress read from a command file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordedKey {
+    Character(char),
+    Zscii(u8),
+}
+
+/// Reads input from a command file.
+pub struct CommandReader {
+    lines: Lines<BufReader<File>>,
+}
+
+impl CommandReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<CommandReader> {
+        Ok(CommandReader {
+            lines: BufReader::new(File::open(path)?).lines(),
+        })
+    }
+
+    /// Read a line of input. Returns None at the end of the file.
+    pub fn read_line(&mut self) -> io::Result<Option<String>> {
+        self.lines
+            .next()
+            .map(|line| line.map(|line| line.trim_end_matches('\r').to_string()))
+            .transpose()
+    }
+
+    /// Read a single keypress. Returns None at the end of the file.
+    pub fn read_key(&mut self) -> io::Result<Option<RecordedKey>> {
+        let line = match self.read_line()? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        let code = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+            .and_then(|code| code.parse().ok());
+        Ok(Some(match (code, line.chars().next()) {
+            (Some(code), _) => RecordedKey::Zscii(code),
+            (None, Some(c)) => RecordedKey::Character(c),
+            // An empty line is a press of the return key.
+            (None, None) => RecordedKey::Zscii(13),
+        }))
+    }
+}
+
+/// Writes the player's input to a command file.
+pub struct CommandWriter {
+    file: BufWriter<File>,
+}
+
+impl CommandWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<CommandWriter> {
+        Ok(CommandWriter {
+            file: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.file, "{}", line)?;
+        // Flush after every command, so the recording survives a crash.
+        self.file.flush()
+    }
+
+    /// Record a keypress, given as a ZSCII code.
+    pub fn write_key(&mut self, zscii: u8) -> io::Result<()> {
+        match zscii {
+            b'[' => writeln!(self.file, "[{}]", zscii)?,
+            32..=126 => writeln!(self.file, "{}", zscii as char)?,
+            _ => writeln!(self.file, "[{}]", zscii)?,
+        }
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn recording_is_replayed() {
+        let path = env::temp_dir().join(format!("zanthe-commands-{}.rec", process::id()));
+        let mut writer = CommandWriter::create(&path).unwrap();
+        writer.write_line("open the door").unwrap();
+        writer.write_line("").unwrap();
+        for key in [b'y', b' ', b'[', 13, 129, 252] {
+            writer.write_key(key).unwrap();
+        }
+        drop(writer);
+
+        let mut reader = CommandReader::open(&path).unwrap();
+        assert_eq!(
+            reader.read_line().unwrap().as_deref(),
+            Some("open the door")
+        );
+        // An empty line read as a key is the return key.
+        assert_eq!(reader.read_key().unwrap(), Some(RecordedKey::Zscii(13)));
+        for key in [
+            RecordedKey::Character('y'),
+            RecordedKey::Character(' '),
+            RecordedKey::Zscii(b'['),
+            RecordedKey::Zscii(13),
+            RecordedKey::Zscii(129),
+            RecordedKey::Zscii(252),
+        ] {
+            assert_eq!(reader.read_key().unwrap(), Some(key));
+        }
+        assert_eq!(reader.read_line().unwrap(), None);
+        assert_eq!(reader.read_key().unwrap(), None);
+        fs::remove_file(path).unwrap();
+    }
+}
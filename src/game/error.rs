@@ -8,10 +8,12 @@ pub struct GameError {
 }
 
 pub enum GameErrorKind {
-    VersionSix,
     InvalidFile,
+    InvalidSaveFile(String),
     InvalidOperation(String),
     IOError(io::Error),
+    /// The player's input has run out, so the game can't go on.
+    EndOfInput,
 }
 
 impl GameError {
@@ -29,9 +31,9 @@ impl GameError {
         }
     }
 
-    pub fn version_six() -> Self {
+    pub fn invalid_save_file<T: Into<String>>(value: T) -> Self {
         GameError {
-            kind: GameErrorKind::VersionSix,
+            kind: GameErrorKind::InvalidSaveFile(value.into()),
             detail: None,
         }
     }
@@ -43,6 +45,17 @@ impl GameError {
         }
     }
 
+    pub fn end_of_input() -> Self {
+        GameError {
+            kind: GameErrorKind::EndOfInput,
+            detail: None,
+        }
+    }
+
+    pub fn is_end_of_input(&self) -> bool {
+        matches!(self.kind, GameErrorKind::EndOfInput)
+    }
+
     pub fn detail<T: Into<String>>(&mut self, detail: T) -> &mut Self {
         self.detail = Some(detail.into());
         self
@@ -55,16 +68,19 @@ impl Display for GameError {
             f,
             "{}",
             match &self.kind {
-                GameErrorKind::VersionSix => "Version 6 story files are not supported".to_string(),
                 GameErrorKind::InvalidFile => {
                     "The file you have specified is not a supported Z-Code file".to_string()
                 }
+                GameErrorKind::InvalidSaveFile(e) => {
+                    format!("Invalid save file: {}", e)
+                }
                 GameErrorKind::InvalidOperation(e) => {
                     format!("Error while running game: {}", e)
                 }
                 GameErrorKind::IOError(e) => {
                     format!("I/O Error: {}", e)
                 }
+                GameErrorKind::EndOfInput => "End of input".to_string(),
             }
         )
     }
@@ -0,0 +1,701 @@
+//! The version 6 screen: a pixel-based model of the screen and its eight windows, drawn into
+//! memory. Pictures come from a Blorb resource file. Text is drawn as solid blocks, one per
+//! character, which is enough to see the layout; the interface shows the text itself.
+
+mod png;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use tracing::warn;
+
+use crate::game::error::GameError;
+use crate::game::Result;
+use crate::interface::Colour;
+use crate::loader::blorb::{ChunkKind, PictureFormat};
+use crate::loader::BlorbLoader;
+
+/// The width of a character, in pixels.
+pub const FONT_WIDTH: u16 = 8;
+/// The height of a line of text, in pixels.
+pub const FONT_HEIGHT: u16 = 16;
+
+/// Window properties, numbered as for `get_wind_prop` and `put_wind_prop`.
+pub mod property {
+    pub const Y: usize = 0;
+    pub const X: usize = 1;
+    pub const Y_SIZE: usize = 2;
+    pub const X_SIZE: usize = 3;
+    pub const Y_CURSOR: usize = 4;
+    pub const X_CURSOR: usize = 5;
+    pub const LEFT_MARGIN: usize = 6;
+    pub const RIGHT_MARGIN: usize = 7;
+    pub const _NEWLINE_INTERRUPT: usize = 8;
+    pub const _INTERRUPT_COUNTDOWN: usize = 9;
+    pub const TEXT_STYLE: usize = 10;
+    pub const COLOUR_DATA: usize = 11;
+    pub const FONT_NUMBER: usize = 12;
+    pub const FONT_SIZE: usize = 13;
+    pub const ATTRIBUTES: usize = 14;
+    pub const _LINE_COUNT: usize = 15;
+    pub const TRUE_FOREGROUND: usize = 16;
+    pub const TRUE_BACKGROUND: usize = 17;
+}
+
+/// Bits of the window attributes property, as set by `window_style`.
+pub mod attribute {
+    pub const WRAPPING: u16 = 1;
+    pub const SCROLLING: u16 = 2;
+    pub const TRANSCRIPT: u16 = 4;
+    pub const BUFFERED: u16 = 8;
+}
+
+const PROPERTY_COUNT: usize = 18;
+const WINDOW_COUNT: usize = 8;
+
+/// A grid of pixels, each held as 0xAARRGGBB.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize, colour: u32) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![colour; width * height],
+        }
+    }
+
+    /// Fill a rectangle, given in pixels from the top left corner. Anything off the edge of the
+    /// image is ignored.
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, colour: u32) {
+        for row in y.min(self.height)..(y + height).min(self.height) {
+            let start = row * self.width;
+            self.pixels[start + x.min(self.width)..start + (x + width).min(self.width)]
+                .fill(colour);
+        }
+    }
+
+    /// Draw another image on top of this one. Pixels that are more than half transparent are
+    /// left out.
+    fn draw(&mut self, image: &Image, x: usize, y: usize) {
+        for row in 0..image.height.min(self.height.saturating_sub(y)) {
+            for column in 0..image.width.min(self.width.saturating_sub(x)) {
+                let pixel = image.pixels[row * image.width + column];
+                if pixel >> 24 >= 0x80 {
+                    self.pixels[(y + row) * self.width + x + column] = pixel;
+                }
+            }
+        }
+    }
+
+    /// Move the content of a rectangle up (or down, if the distance is negative), filling the
+    /// space left behind with a colour.
+    fn scroll(&mut self, area: Rectangle, distance: i32, colour: u32) {
+        let Rectangle {
+            x,
+            y,
+            width,
+            height,
+        } = area.clip(self.width, self.height);
+        let shift = distance.unsigned_abs() as usize;
+        if shift >= height {
+            self.fill(x, y, width, height, colour);
+            return;
+        }
+        let copy_row = |image: &mut Image, from: usize, to: usize| {
+            let from = from * image.width + x;
+            image
+                .pixels
+                .copy_within(from..from + width, to * image.width + x);
+        };
+        if distance > 0 {
+            for row in y..y + height - shift {
+                copy_row(self, row + shift, row);
+            }
+            self.fill(x, y + height - shift, width, shift, colour);
+        } else {
+            for row in (y + shift..y + height).rev() {
+                copy_row(self, row - shift, row);
+            }
+            self.fill(x, y, width, shift, colour);
+        }
+    }
+}
+
+/// A rectangle on the screen, in pixels from the top left corner.
+#[derive(Debug, Clone, Copy)]
+struct Rectangle {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Rectangle {
+    fn clip(self, width: usize, height: usize) -> Rectangle {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        Rectangle {
+            x,
+            y,
+            width: self.width.min(width - x),
+            height: self.height.min(height - y),
+        }
+    }
+}
+
+/// Convert a 15-bit colour to a pixel.
+fn pixel(true_colour: u16) -> u32 {
+    let (r, g, b) = Colour::True(true_colour).rgb().unwrap();
+    u32::from_be_bytes([0xFF, r, g, b])
+}
+
+/// Writes each frame shown to the player to a numbered PNG file, so the screen can be checked
+/// without a display.
+struct FrameDump {
+    directory: PathBuf,
+    count: u32,
+}
+
+impl FrameDump {
+    fn write(&mut self, image: &Image) -> Result<()> {
+        self.count += 1;
+        let path = self.directory.join(format!("frame{:05}.png", self.count));
+        fs::write(path, png::encode(image)?)?;
+        Ok(())
+    }
+}
+
+/// The version 6 screen model.
+pub struct Graphics {
+    screen: Image,
+    windows: [[u16; PROPERTY_COUNT]; WINDOW_COUNT],
+    resources: Option<Rc<BlorbLoader>>,
+    /// Pictures decoded so far, or None for pictures that couldn't be loaded.
+    pictures: HashMap<u16, Option<Rc<Image>>>,
+    mouse_window: u16,
+    buffered: bool,
+    /// Whether the screen has changed since it was last shown.
+    changed: bool,
+    frames: Option<FrameDump>,
+}
+
+impl Graphics {
+    /// Create a screen of the given size (in pixels). If a frame directory is given, each frame
+    /// is written to it as a PNG file.
+    pub fn new(
+        width: u16,
+        height: u16,
+        resources: Option<Rc<BlorbLoader>>,
+        frame_directory: Option<PathBuf>,
+    ) -> Result<Graphics> {
+        let frames = match frame_directory {
+            Some(directory) => {
+                fs::create_dir_all(&directory)?;
+                Some(FrameDump {
+                    directory,
+                    count: 0,
+                })
+            }
+            None => None,
+        };
+        let mut graphics = Graphics {
+            screen: Image::new(width as usize, height as usize, 0),
+            windows: [[0; PROPERTY_COUNT]; WINDOW_COUNT],
+            resources,
+            pictures: HashMap::new(),
+            mouse_window: 1,
+            buffered: true,
+            changed: true,
+            frames,
+        };
+        graphics.reset();
+        Ok(graphics)
+    }
+
+    /// Put every window back to its starting position, and clear the screen.
+    pub fn reset(&mut self) {
+        let (width, height) = (self.screen.width as u16, self.screen.height as u16);
+        for (number, window) in self.windows.iter_mut().enumerate() {
+            *window = [0; PROPERTY_COUNT];
+            window[property::Y] = 1;
+            window[property::X] = 1;
+            window[property::Y_CURSOR] = 1;
+            window[property::X_CURSOR] = 1;
+            window[property::FONT_NUMBER] = 1;
+            window[property::FONT_SIZE] = (FONT_HEIGHT << 8) | FONT_WIDTH;
+            // White on black, matching the default colours in the header.
+            window[property::COLOUR_DATA] = (2 << 8) | 9;
+            window[property::TRUE_FOREGROUND] = 0x7FFF;
+            window[property::TRUE_BACKGROUND] = 0x0000;
+            match number {
+                0 => {
+                    window[property::Y_SIZE] = height;
+                    window[property::X_SIZE] = width;
+                    window[property::ATTRIBUTES] = attribute::WRAPPING
+                        | attribute::SCROLLING
+                        | attribute::TRANSCRIPT
+                        | attribute::BUFFERED;
+                }
+                1 => window[property::X_SIZE] = width,
+                _ => {}
+            }
+        }
+        self.clear_screen(0);
+    }
+
+    pub fn size(&self) -> (u16, u16) {
+        (self.screen.width as u16, self.screen.height as u16)
+    }
+
+    /// The image of the whole screen.
+    pub fn screen(&self) -> &Image {
+        &self.screen
+    }
+
+    fn window(&self, window: u16) -> Result<&[u16; PROPERTY_COUNT]> {
+        self.windows
+            .get(window as usize)
+            .ok_or_else(|| GameError::invalid_operation(format!("Invalid window {}", window)))
+    }
+
+    fn window_mut(&mut self, window: u16) -> Result<&mut [u16; PROPERTY_COUNT]> {
+        self.windows
+            .get_mut(window as usize)
+            .ok_or_else(|| GameError::invalid_operation(format!("Invalid window {}", window)))
+    }
+
+    pub fn property(&self, window: u16, property: u16) -> Result<u16> {
+        self.window(window)?
+            .get(property as usize)
+            .copied()
+            .ok_or_else(|| {
+                GameError::invalid_operation(format!("Invalid window property {}", property))
+            })
+    }
+
+    pub fn set_property(&mut self, window: u16, property: u16, value: u16) -> Result<()> {
+        let slot = self
+            .window_mut(window)?
+            .get_mut(property as usize)
+            .ok_or_else(|| {
+                GameError::invalid_operation(format!("Invalid window property {}", property))
+            })?;
+        *slot = value;
+        Ok(())
+    }
+
+    /// The area of the screen covered by a window.
+    fn area(&self, window: u16) -> Result<Rectangle> {
+        let properties = self.window(window)?;
+        Ok(Rectangle {
+            x: properties[property::X].saturating_sub(1) as usize,
+            y: properties[property::Y].saturating_sub(1) as usize,
+            width: properties[property::X_SIZE] as usize,
+            height: properties[property::Y_SIZE] as usize,
+        }
+        .clip(self.screen.width, self.screen.height))
+    }
+
+    fn background(&self, window: u16) -> Result<u32> {
+        Ok(pixel(self.window(window)?[property::TRUE_BACKGROUND]))
+    }
+
+    /// Set a window's colours. A colour of None is left unchanged, and the default colour is
+    /// white text on black.
+    pub fn set_colour(
+        &mut self,
+        window: u16,
+        foreground: Option<Colour>,
+        background: Option<Colour>,
+    ) -> Result<()> {
+        let properties = self.window_mut(window)?;
+        if let Some(foreground) = foreground {
+            properties[property::TRUE_FOREGROUND] = foreground.true_colour().unwrap_or(0x7FFF);
+        }
+        if let Some(background) = background {
+            properties[property::TRUE_BACKGROUND] = background.true_colour().unwrap_or(0x0000);
+        }
+        Ok(())
+    }
+
+    /// Move a window's cursor, keeping it inside the window.
+    pub fn set_cursor(&mut self, window: u16, y: u16, x: u16) -> Result<()> {
+        let properties = self.window_mut(window)?;
+        properties[property::Y_CURSOR] = y.max(1);
+        properties[property::X_CURSOR] = x.max(1);
+        Ok(())
+    }
+
+    /// Change the size of the upper window (window 1), in pixels. The lower window (window 0)
+    /// takes the rest of the screen.
+    pub fn split(&mut self, height: u16) -> Result<()> {
+        let screen_height = self.screen.height as u16;
+        let height = height.min(screen_height);
+        let upper = self.window_mut(1)?;
+        upper[property::Y] = 1;
+        upper[property::Y_SIZE] = height;
+        if upper[property::Y_CURSOR] > height {
+            upper[property::Y_CURSOR] = 1;
+        }
+        let lower = self.window_mut(0)?;
+        lower[property::Y] = height + 1;
+        lower[property::Y_SIZE] = screen_height - height;
+        if lower[property::Y_CURSOR] > lower[property::Y_SIZE] {
+            lower[property::Y_CURSOR] = 1;
+        }
+        Ok(())
+    }
+
+    /// Clear the whole screen to the background colour of a window.
+    pub fn clear_screen(&mut self, window: u16) {
+        let colour = self.background(window).unwrap_or(0xFF00_0000);
+        self.screen.pixels.fill(colour);
+        self.changed = true;
+    }
+
+    /// Clear a window to its background colour, and move its cursor to the top left.
+    pub fn erase_window(&mut self, window: u16) -> Result<()> {
+        let area = self.area(window)?;
+        let colour = self.background(window)?;
+        self.screen
+            .fill(area.x, area.y, area.width, area.height, colour);
+        let properties = self.window_mut(window)?;
+        properties[property::Y_CURSOR] = 1;
+        properties[property::X_CURSOR] = properties[property::LEFT_MARGIN] + 1;
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Clear part of the line a window's cursor is on, from the cursor to the right margin or
+    /// for the given number of pixels, whichever is shorter.
+    pub fn erase_line(&mut self, window: u16, pixels: Option<u16>) -> Result<()> {
+        let properties = *self.window(window)?;
+        let area = self.area(window)?;
+        let start = properties[property::X_CURSOR].saturating_sub(1);
+        let end = properties[property::X_SIZE].saturating_sub(properties[property::RIGHT_MARGIN]);
+        let width = end.saturating_sub(start);
+        let width = pixels.map_or(width, |pixels| pixels.min(width));
+        let colour = self.background(window)?;
+        self.screen.fill(
+            area.x + start as usize,
+            area.y + properties[property::Y_CURSOR].saturating_sub(1) as usize,
+            width as usize,
+            FONT_HEIGHT as usize,
+            colour,
+        );
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Scroll a window up by a number of pixels (or down, if negative).
+    pub fn scroll(&mut self, window: u16, pixels: i16) -> Result<()> {
+        let area = self.area(window)?;
+        let colour = self.background(window)?;
+        self.screen.scroll(area, pixels.into(), colour);
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Print text in a window at its cursor, wrapping and scrolling if the window's attributes
+    /// allow. Each character is drawn as a block.
+    pub fn print(&mut self, window: u16, text: &str) -> Result<()> {
+        for c in text.chars() {
+            if c == '\n' {
+                self.new_line(window)?;
+                continue;
+            }
+            let properties = *self.window(window)?;
+            let right_edge =
+                properties[property::X_SIZE].saturating_sub(properties[property::RIGHT_MARGIN]);
+            if properties[property::X_CURSOR] + FONT_WIDTH - 1 > right_edge {
+                if properties[property::ATTRIBUTES] & attribute::WRAPPING == 0 {
+                    continue;
+                }
+                self.new_line(window)?;
+            }
+            self.draw_character(window, c)?;
+            self.window_mut(window)?[property::X_CURSOR] += FONT_WIDTH;
+        }
+        Ok(())
+    }
+
+    fn draw_character(&mut self, window: u16, c: char) -> Result<()> {
+        let properties = *self.window(window)?;
+        let area = self.area(window)?;
+        // The cursor is 1-based, but a game may have set it to 0.
+        let x = (area.x + properties[property::X_CURSOR] as usize).saturating_sub(1);
+        let y = (area.y + properties[property::Y_CURSOR] as usize).saturating_sub(1);
+        // Keep inside the window.
+        let cell = Rectangle {
+            x,
+            y,
+            width: (FONT_WIDTH as usize).min((area.x + area.width).saturating_sub(x)),
+            height: (FONT_HEIGHT as usize).min((area.y + area.height).saturating_sub(y)),
+        };
+        let (mut foreground, mut background) = (
+            pixel(properties[property::TRUE_FOREGROUND]),
+            pixel(properties[property::TRUE_BACKGROUND]),
+        );
+        if properties[property::TEXT_STYLE] & 1 != 0 {
+            // Reverse video
+            (foreground, background) = (background, foreground);
+        }
+        self.screen
+            .fill(cell.x, cell.y, cell.width, cell.height, background);
+        if !c.is_whitespace() {
+            // Leave a gap around the block, so characters stay apart.
+            let height = if c.is_lowercase() { 7 } else { 10 };
+            self.screen.fill(
+                cell.x + 1,
+                cell.y + 13 - height,
+                cell.width.saturating_sub(2),
+                height.min(cell.height.saturating_sub(13 - height)),
+                foreground,
+            );
+        }
+        self.changed = true;
+        Ok(())
+    }
+
+    fn new_line(&mut self, window: u16) -> Result<()> {
+        let properties = self.window_mut(window)?;
+        properties[property::X_CURSOR] = properties[property::LEFT_MARGIN] + 1;
+        properties[property::Y_CURSOR] += FONT_HEIGHT;
+        let bottom = properties[property::Y_CURSOR] + FONT_HEIGHT - 1;
+        if bottom > properties[property::Y_SIZE]
+            && properties[property::ATTRIBUTES] & attribute::SCROLLING != 0
+        {
+            let overflow = bottom - properties[property::Y_SIZE];
+            properties[property::Y_CURSOR] -= overflow;
+            self.scroll(window, overflow as i16)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of pictures in the resource file.
+    pub fn picture_count(&self) -> u16 {
+        self.resources
+            .iter()
+            .flat_map(|resources| resources.chunks())
+            .filter(|chunk| matches!(chunk.kind, ChunkKind::Picture { .. }))
+            .map(|chunk| chunk.number as u16)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Load a picture from the resource file, if it has one with that number.
+    pub fn picture(&mut self, number: u16) -> Option<Rc<Image>> {
+        if let Some(picture) = self.pictures.get(&number) {
+            return picture.clone();
+        }
+        let picture = self.load_picture(number).map(Rc::new);
+        self.pictures.insert(number, picture.clone());
+        picture
+    }
+
+    fn load_picture(&self, number: u16) -> Option<Image> {
+        let chunk = self.resources.as_ref()?.picture(number.into())?;
+        let format = match chunk.kind {
+            ChunkKind::Picture { format } => format,
+            _ => return None,
+        };
+        match format {
+            PictureFormat::Png => png::decode(chunk.data)
+                .map_err(|e| warn!("Could not decode picture {}: {}", number, e))
+                .ok(),
+            // JPEG pictures aren't decoded. They're shown as grey boxes of the right size.
+            PictureFormat::Jpeg => {
+                let (width, height) = jpeg_size(chunk.data)?;
+                Some(Image::new(width, height, 0xFF80_8080))
+            }
+            // A placeholder is an empty rectangle.
+            PictureFormat::Placeholder if chunk.data.len() >= 8 => {
+                let width = u32::from_be_bytes(chunk.data[0..4].try_into().unwrap());
+                let height = u32::from_be_bytes(chunk.data[4..8].try_into().unwrap());
+                Some(Image::new(width as usize, height as usize, 0))
+            }
+            _ => {
+                warn!("Picture {} is in an unsupported format", number);
+                None
+            }
+        }
+    }
+
+    /// Draw a picture with its top left corner at a position in a window. Either coordinate may
+    /// be None, for the window's cursor position.
+    pub fn draw_picture(
+        &mut self,
+        window: u16,
+        number: u16,
+        y: Option<u16>,
+        x: Option<u16>,
+    ) -> Result<()> {
+        let picture = match self.picture(number) {
+            Some(picture) => picture,
+            None => {
+                warn!("Picture {} not found", number);
+                return Ok(());
+            }
+        };
+        let (x, y) = self.position(window, y, x)?;
+        self.screen.draw(&picture, x, y);
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Fill the area a picture would cover with the window's background colour.
+    pub fn erase_picture(
+        &mut self,
+        window: u16,
+        number: u16,
+        y: Option<u16>,
+        x: Option<u16>,
+    ) -> Result<()> {
+        let picture = match self.picture(number) {
+            Some(picture) => picture,
+            None => return Ok(()),
+        };
+        let (x, y) = self.position(window, y, x)?;
+        let colour = self.background(window)?;
+        self.screen
+            .fill(x, y, picture.width, picture.height, colour);
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Work out a position on the screen, in pixels, from a position in a window.
+    fn position(&self, window: u16, y: Option<u16>, x: Option<u16>) -> Result<(usize, usize)> {
+        let properties = self.window(window)?;
+        let y = y.unwrap_or(properties[property::Y_CURSOR]);
+        let x = x.unwrap_or(properties[property::X_CURSOR]);
+        Ok((
+            (properties[property::X] + x).saturating_sub(2) as usize,
+            (properties[property::Y] + y).saturating_sub(2) as usize,
+        ))
+    }
+
+    pub fn mouse_window(&self) -> u16 {
+        self.mouse_window
+    }
+
+    pub fn set_mouse_window(&mut self, window: u16) {
+        self.mouse_window = window;
+    }
+
+    /// Turn screen buffering on or off, returning the previous setting. While the screen is
+    /// buffered, it's only shown when the game waits for input.
+    pub fn set_buffered(&mut self, buffered: bool) -> bool {
+        std::mem::replace(&mut self.buffered, buffered)
+    }
+
+    pub fn buffered(&self) -> bool {
+        self.buffered
+    }
+
+    /// Show the screen, if it has changed since it was last shown.
+    pub fn present(&mut self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        self.changed = false;
+        match &mut self.frames {
+            Some(frames) => frames.write(&self.screen),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Read the size of a JPEG image from its frame header, as (width, height).
+fn jpeg_size(data: &[u8]) -> Option<(usize, usize)> {
+    let mut cursor = 2;
+    while cursor + 9 < data.len() {
+        if data[cursor] != 0xFF {
+            return None;
+        }
+        let marker = data[cursor + 1];
+        let length = u16::from_be_bytes([data[cursor + 2], data[cursor + 3]]) as usize;
+        // Start of frame markers, other than those that aren't frames (DHT, JPG and DAC).
+        if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let height = u16::from_be_bytes([data[cursor + 5], data[cursor + 6]]);
+            let width = u16::from_be_bytes([data[cursor + 7], data[cursor + 8]]);
+            return Some((width as usize, height as usize));
+        }
+        cursor += 2 + length;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: u32 = 0xFF00_0000;
+    const RED: u32 = 0xFFFF_0000;
+
+    /// A Blorb file holding one PNG picture, numbered 1.
+    fn blorb(picture: &[u8]) -> BlorbLoader {
+        // The picture's chunk follows the FORM header and the 16-byte index chunk.
+        let mut index = 1u32.to_be_bytes().to_vec();
+        index.extend(b"Pict");
+        index.extend(1u32.to_be_bytes());
+        index.extend(36u32.to_be_bytes());
+        let mut body = b"IFRS".to_vec();
+        for (kind, data) in [(b"RIdx", &index[..]), (b"PNG ", picture)] {
+            body.extend(kind);
+            body.extend((data.len() as u32).to_be_bytes());
+            body.extend(data);
+            if data.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+        let mut file = b"FORM".to_vec();
+        file.extend((body.len() as u32).to_be_bytes());
+        file.extend(body);
+        BlorbLoader::new(file).unwrap()
+    }
+
+    #[test]
+    fn frame_dump() {
+        let picture = png::encode(&Image::new(2, 2, RED)).unwrap();
+        let directory = tempfile::tempdir().unwrap();
+        let mut graphics = Graphics::new(
+            4,
+            3,
+            Some(Rc::new(blorb(&picture))),
+            Some(directory.path().to_path_buf()),
+        )
+        .unwrap();
+        graphics.draw_picture(0, 1, Some(2), Some(3)).unwrap();
+        graphics.present().unwrap();
+        // Nothing has changed since, so there's no second frame.
+        graphics.present().unwrap();
+
+        let frame = fs::read(directory.path().join("frame00001.png")).unwrap();
+        let frame = png::decode(&frame).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(frame.pixels, [
+            BLACK, BLACK, BLACK, BLACK,
+            BLACK, BLACK, RED, RED,
+            BLACK, BLACK, RED, RED,
+        ]);
+        assert!(!directory.path().join("frame00002.png").exists());
+    }
+
+    #[test]
+    fn print_at_cursor_zero() {
+        let mut graphics = Graphics::new(32, 32, None, None).unwrap();
+        // The cursor can't be put at 0 with set_cursor, but it can with put_wind_prop.
+        graphics
+            .set_property(0, property::X_CURSOR as u16, 0)
+            .unwrap();
+        graphics.print(0, "a").unwrap();
+    }
+}
@@ -0,0 +1,104 @@
+//! Converting between PNG files and images: decoding the pictures in Blorb files, and encoding
+//! frames.
+
+use ::png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+
+use crate::game::error::GameError;
+use crate::game::graphics::Image;
+use crate::game::Result;
+
+fn invalid_png<E: std::fmt::Display>(error: E) -> GameError {
+    GameError::invalid_operation(format!("Invalid PNG picture: {}", error))
+}
+
+/// Decode a PNG file into an image.
+pub fn decode(data: &[u8]) -> Result<Image> {
+    let mut decoder = Decoder::new(data);
+    // Every image comes out as 8-bit greyscale or RGB, with or without alpha.
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(invalid_png)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(invalid_png)?;
+
+    let channels = info.color_type.samples();
+    let argb = |a: u8, r: u8, g: u8, b: u8| u32::from_be_bytes([a, r, g, b]);
+    let mut pixels = Vec::with_capacity(info.width as usize * info.height as usize);
+    for row in buffer.chunks(info.line_size).take(info.height as usize) {
+        for sample in row.chunks(channels).take(info.width as usize) {
+            pixels.push(match info.color_type {
+                ColorType::Grayscale => argb(255, sample[0], sample[0], sample[0]),
+                ColorType::GrayscaleAlpha => argb(sample[1], sample[0], sample[0], sample[0]),
+                ColorType::Rgba => argb(sample[3], sample[0], sample[1], sample[2]),
+                _ => argb(255, sample[0], sample[1], sample[2]),
+            });
+        }
+    }
+    Ok(Image {
+        width: info.width as usize,
+        height: info.height as usize,
+        pixels,
+    })
+}
+
+/// Encode an image as an 8-bit RGB PNG file. Transparency is dropped.
+pub fn encode(image: &Image) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(image.pixels.len() * 3);
+    for pixel in &image.pixels {
+        let [_, r, g, b] = pixel.to_be_bytes();
+        data.extend([r, g, b]);
+    }
+
+    let mut file = Vec::new();
+    let mut encoder = Encoder::new(&mut file, image.width as u32, image.height as u32);
+    encoder.set_color(ColorType::Rgb);
+    encoder.set_depth(BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(invalid_png)?;
+    writer.write_image_data(&data).map_err(invalid_png)?;
+    writer.finish().map_err(invalid_png)?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: (0..width * height)
+                .map(|i| 0xFF00_0000 | ((i as u32 * 0x0001_0307) & 0x00FF_FFFF))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let original = image(5, 3);
+        let decoded = decode(&encode(&original).unwrap()).unwrap();
+        assert_eq!(decoded.width, 5);
+        assert_eq!(decoded.height, 3);
+        assert_eq!(decoded.pixels, original.pixels);
+    }
+
+    #[test]
+    fn palette_with_transparency() {
+        let mut file = Vec::new();
+        let mut encoder = Encoder::new(&mut file, 2, 1);
+        encoder.set_color(ColorType::Indexed);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.set_palette(vec![0x10, 0x20, 0x30, 0x40, 0x50, 0x60]);
+        encoder.set_trns(vec![0x00]);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[0, 1]).unwrap();
+        writer.finish().unwrap();
+
+        let decoded = decode(&file).unwrap();
+        assert_eq!(decoded.pixels, [0x0010_2030, 0xFF40_5060]);
+    }
+
+    #[test]
+    fn not_a_png() {
+        assert!(decode(b"GIF89a").is_err());
+    }
+}
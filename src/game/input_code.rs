@@ -1,3 +1,5 @@
+/// A key the player can press, covering the whole ZSCII input set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InputCode {
     Character(char),
     Delete,
@@ -7,4 +9,23 @@ pub enum InputCode {
     CursorDown,
     CursorLeft,
     CursorRight,
+    /// A function key, from F1 to F12.
+    FunctionKey(u8),
+    /// A number key on the keypad, from 0 to 9.
+    Keypad(u8),
+    /// A mouse click. The interface knows where it was.
+    DoubleClick,
+    SingleClick,
+}
+
+impl InputCode {
+    /// The keys that a game can choose to end a line of input with, besides Enter.
+    pub fn terminating_keys() -> impl Iterator<Item = InputCode> {
+        use InputCode::*;
+        [CursorUp, CursorDown, CursorLeft, CursorRight]
+            .into_iter()
+            .chain((1..=12).map(FunctionKey))
+            .chain((0..=9).map(Keypad))
+            .chain([DoubleClick, SingleClick])
+    }
 }
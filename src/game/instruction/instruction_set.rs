@@ -2,6 +2,7 @@ mod common;
 mod version_gte3;
 mod version_gte4;
 mod version_gte5;
+mod version_gte6;
 
 use crate::game::instruction::{Instruction, OpCode};
 
@@ -35,6 +36,12 @@ impl InstructionSet {
             }
         }
 
+        if version >= 6 {
+            for (code, instruction) in version_gte6::instructions() {
+                instructions[code.lookup_value()] = Some(instruction);
+            }
+        }
+
         InstructionSet { instructions }
     }
 
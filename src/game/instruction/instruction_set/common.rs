@@ -13,7 +13,7 @@ use crate::game::instruction::{
     Instruction, OperandSet,
     Result::{self as InstructionResult, *},
 };
-use crate::game::state::GameState;
+use crate::game::state::{GameState, TimedInput};
 
 pub fn instructions() -> Vec<(OpCode, Instruction)> {
     use Instruction::*;
@@ -63,14 +63,18 @@ pub fn instructions() -> Vec<(OpCode, Instruction)> {
         (ZeroOp(0x2), StringLiteral(&print, "PRINT")),
         (ZeroOp(0x3), StringLiteral(&print_ret, "PRINT_RET")),
         (ZeroOp(0x4), Normal(&nop, "NOP")),
+        (ZeroOp(0x5), Branch(&save, "SAVE")),
+        (ZeroOp(0x6), Branch(&restore, "RESTORE")),
         (ZeroOp(0x7), Normal(&restart, "RESTART")),
         (ZeroOp(0x8), Normal(&ret_popped, "RET_POPPED")),
         (ZeroOp(0xA), Normal(&quit, "QUIT")),
         (ZeroOp(0xB), Normal(&new_line, "NEW_LINE")),
+        (ZeroOp(0xC), Normal(&show_status, "SHOW_STATUS")),
         (VarOp(0x0), Store(&call, "CALL")),
         (VarOp(0x1), Normal(&storew, "STOREW")),
         (VarOp(0x2), Normal(&storeb, "STOREB")),
         (VarOp(0x3), Normal(&put_prop, "PUT_PROP")),
+        (VarOp(0x4), Normal(&sread, "SREAD")),
         (VarOp(0x5), Normal(&print_char, "PRINT_CHAR")),
         (VarOp(0x6), Normal(&print_num, "PRINT_NUM")),
         (VarOp(0x7), Store(&random, "RANDOM")),
@@ -643,9 +647,8 @@ pub fn dec(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResu
 pub fn print_addr(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let address = ops.pull()?.unsigned(state)? as usize;
 
-    state
-        .interface
-        .print(&state.memory.extract_string(address, true)?.0)?;
+    let string = state.memory.extract_string(address, true)?.0;
+    state.print(&string)?;
 
     Ok(Continue)
 }
@@ -665,9 +668,8 @@ pub fn remove_obj(state: &mut GameState, mut ops: OperandSet) -> Result<Instruct
 /// 1OP:138 Print the short name of the given object.
 pub fn print_obj(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let object = ops.pull()?.unsigned(state)?;
-    state
-        .interface
-        .print(&state.memory.object_short_name(object)?)?;
+    let name = state.memory.object_short_name(object)?;
+    state.print(&name)?;
 
     Ok(Continue)
 }
@@ -687,10 +689,9 @@ pub fn jump(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionRes
 /// 1OP:141 Prints a string stored at a padded address.
 pub fn print_paddr(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let address = ops.pull()?.unsigned(state)?;
-    let address = state.memory.unpack_address(address.into());
-    state
-        .interface
-        .print(&state.memory.extract_string(address, true)?.0)?;
+    let address = state.memory.unpack_string_address(address.into());
+    let string = state.memory.extract_string(address, true)?.0;
+    state.print(&string)?;
 
     Ok(Continue)
 }
@@ -731,14 +732,14 @@ pub fn rfalse(_: &mut GameState, _: OperandSet) -> Result<InstructionResult> {
 
 /// 0OP:178 Prints a string stored immediately after the instruction.
 pub fn print(state: &mut GameState, string: String) -> Result<InstructionResult> {
-    state.interface.print(&string)?;
+    state.print(&string)?;
     Ok(Continue)
 }
 
 /// 0OP:179 Prints a literal string, prints a newline then returns from the current routine.
 pub fn print_ret(state: &mut GameState, string: String) -> Result<InstructionResult> {
-    state.interface.print(&string)?;
-    state.interface.print("\n")?;
+    state.print(&string)?;
+    state.print("\n")?;
 
     Ok(Return(1))
 }
@@ -748,6 +749,34 @@ pub fn nop(_state: &mut GameState, _: OperandSet) -> Result<InstructionResult> {
     Ok(Continue)
 }
 
+/// 0OP:181 (v1-3) Save the game, and branch if successful.
+pub fn save(
+    state: &mut GameState,
+    _: OperandSet,
+    expected: bool,
+    offset: i16,
+) -> Result<InstructionResult> {
+    let condition = state.save()?;
+
+    Ok(state
+        .frame()
+        .conditional_branch(offset, condition, expected))
+}
+
+/// 0OP:182 (v1-3) Restore a saved game. If successful, execution continues from the save
+/// instruction. Otherwise, branch if the restore failed.
+pub fn restore(
+    state: &mut GameState,
+    _: OperandSet,
+    expected: bool,
+    offset: i16,
+) -> Result<InstructionResult> {
+    match state.restore()? {
+        Some(result) => Ok(result),
+        None => Ok(state.frame().conditional_branch(offset, false, expected)),
+    }
+}
+
 /// 0OP:183 Restart the game. The only preserved information are the 'transcribing to printer' bit
 /// and the 'use fixed pitch font' bit.
 
@@ -767,7 +796,14 @@ pub fn quit(_: &mut GameState, _: OperandSet) -> Result<InstructionResult> {
 
 /// 0OP:187 Prints a newline
 pub fn new_line(state: &mut GameState, _: OperandSet) -> Result<InstructionResult> {
-    state.interface.print("\n")?;
+    state.print("\n")?;
+
+    Ok(Continue)
+}
+
+/// 0OP:188 (v3) Redraw the status line.
+pub fn show_status(state: &mut GameState, _: OperandSet) -> Result<InstructionResult> {
+    state.show_status()?;
 
     Ok(Continue)
 }
@@ -781,7 +817,7 @@ pub fn call(state: &mut GameState, mut ops: OperandSet, store_to: u8) -> Result<
         return Ok(Continue);
     }
 
-    let address = state.memory.unpack_address(address as usize);
+    let address = state.memory.unpack_routine_address(address as usize);
     let arguments: Vec<u16> = ops
         .map(|op| op.try_unsigned(state))
         .collect::<Result<Vec<Option<u16>>>>()?
@@ -855,13 +891,47 @@ pub fn put_prop(state: &mut GameState, mut ops: OperandSet) -> Result<Instructio
     Ok(Continue)
 }
 
+/// VAR:228 (v1-4) Read a line of input from the user and split it into words. In version 4,
+/// a routine can be called every `time` tenths of a second while waiting.
+pub fn sread(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let text_address = ops.pull()?.unsigned(state)? as usize;
+    let parse_address = ops.pull()?.unsigned(state)? as usize;
+    let time = ops.pull()?.try_unsigned(state)?;
+    let routine = ops.pull()?.try_unsigned(state)?;
+
+    // The first byte of the text buffer is one more than the number of characters allowed.
+    let max_characters = state.memory.get_byte(text_address);
+    if max_characters < 2 {
+        return Err(GameError::invalid_operation(
+            "Text buffer cannot be less than 2 bytes",
+        ));
+    }
+
+    // In versions 1-3, the status line is redrawn before reading input.
+    state.show_status()?;
+    let interrupt = state.interrupt(time, routine);
+    let string = match state.read_command(max_characters as usize - 1, &[], interrupt)? {
+        TimedInput::Done((string, _)) | TimedInput::Aborted((string, _)) => string.to_lowercase(),
+        TimedInput::Halted(result) => return Ok(result),
+    };
+
+    state.memory.write_input_array(text_address, &string)?;
+
+    let max_words = state.memory.get_byte(parse_address);
+    state
+        .memory
+        .parse_string(parse_address, &string, max_words as usize, None, false)?;
+
+    Ok(Continue)
+}
+
 /// VAR:229 Print a ZSCII character
 pub fn print_char(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let char_id = ops.pull()?.unsigned(state)?;
 
     let c = state.memory.alphabet().decode_zscii(char_id)?;
     if let Some(c) = c {
-        state.interface.print_char(c)?;
+        state.print_char(c)?;
     }
 
     Ok(Continue)
@@ -871,7 +941,7 @@ pub fn print_char(state: &mut GameState, mut ops: OperandSet) -> Result<Instruct
 pub fn print_num(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let num = ops.pull()?.signed(state)?;
 
-    state.interface.print(&format!("{}", num))?;
+    state.print(&format!("{}", num))?;
     Ok(Continue)
 }
 
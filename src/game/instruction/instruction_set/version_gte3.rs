@@ -1,5 +1,4 @@
 use crate::game::Result;
-use tracing::warn;
 
 use crate::game::instruction::op_code::OpCode;
 use crate::game::instruction::Instruction;
@@ -14,6 +13,8 @@ pub fn instructions() -> Vec<(OpCode, Instruction)> {
         (VarOp(0xA), Normal(&split_window, "SPLIT_WINDOW")),
         (VarOp(0xB), Normal(&set_window, "SET_WINDOW")),
         (VarOp(0x13), Normal(&output_stream, "OUTPUT_STREAM")),
+        (VarOp(0x14), Normal(&input_stream, "INPUT_STREAM")),
+        (VarOp(0x15), Normal(&sound_effect, "SOUND_EFFECT")),
     ]
 }
 
@@ -45,13 +46,50 @@ pub fn split_window(state: &mut GameState, mut ops: OperandSet) -> Result<Instru
 pub fn set_window(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let window = ops.pull()?.unsigned(state)?;
 
-    state.interface.set_active(window)?;
+    state.set_window(window)?;
     Ok(Continue)
 }
 
-/// VAR:243 Change the output stream
-pub fn output_stream(_state: &mut GameState, mut _ops: OperandSet) -> Result<InstructionResult> {
-    // TODO: Implment
-    warn!("OUTPUT_STREAM UNIMPLEMENTED");
+/// VAR:243 Select (if positive) or deselect (if negative) an output stream
+pub fn output_stream(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let stream = ops.pull()?.signed(state)?;
+    let table = match ops.next() {
+        Some(op) => op.try_unsigned(state)?,
+        None => None,
+    };
+
+    if stream != 0 {
+        state.select_output_stream(stream.unsigned_abs(), stream > 0, table)?;
+    }
+    Ok(Continue)
+}
+
+/// VAR:244 Select the input stream
+pub fn input_stream(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let stream = ops.pull()?.unsigned(state)?;
+
+    state.select_input_stream(stream)?;
+    Ok(Continue)
+}
+
+/// VAR:245 Play, stop or otherwise manage a sound effect. From version 5, the high byte of the
+/// volume is the number of times to play it (0 to play it as often as the sound data says), and
+/// a routine can be given to call when it ends.
+pub fn sound_effect(state: &mut GameState, ops: OperandSet) -> Result<InstructionResult> {
+    let operands = ops
+        .map(|op| op.try_unsigned(state).map(Option::unwrap_or_default))
+        .collect::<Result<Vec<u16>>>()?;
+    let operand = |i: usize| operands.get(i).copied();
+    // With no operands, this is a high bleep.
+    let number = operand(0).unwrap_or(1);
+    let effect = operand(1).unwrap_or(2);
+    let volume = operand(2).unwrap_or(0xFF);
+    let (volume, repeats, routine) = if state.version >= 5 {
+        (volume & 0xFF, (volume >> 8) as u8, operand(3).unwrap_or(0))
+    } else {
+        (volume, 0, 0)
+    };
+
+    state.sound_effect(number, effect, volume, repeats, routine)?;
     Ok(Continue)
 }
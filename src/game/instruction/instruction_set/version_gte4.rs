@@ -5,7 +5,7 @@ use crate::game::error::GameError;
 use crate::game::instruction::op_code::OpCode;
 use crate::game::instruction::Instruction;
 use crate::game::instruction::{OperandSet, Result as InstructionResult, Result::*};
-use crate::game::state::GameState;
+use crate::game::state::{GameState, TimedInput};
 use crate::interface::ClearMode;
 
 pub fn instructions() -> Vec<(OpCode, Instruction)> {
@@ -14,13 +14,18 @@ pub fn instructions() -> Vec<(OpCode, Instruction)> {
     vec![
         (TwoOp(0x19), Store(&call_2s, "CALL_2S")),
         (OneOp(0x8), Store(&call_1s, "CALL_1S")),
+        (ZeroOp(0x5), Store(&save, "SAVE")),
+        (ZeroOp(0x6), Store(&restore, "RESTORE")),
         (VarOp(0x0), Store(&call_vs, "CALL_VS")),
         (VarOp(0xC), Store(&call_vs2, "CALL_VS2")),
         (VarOp(0xD), Normal(&erase_window, "ERASE_WINDOW")),
+        (VarOp(0xE), Normal(&erase_line, "ERASE_LINE")),
         (VarOp(0xF), Normal(&set_cursor, "SET_CURSOR")),
+        (VarOp(0x10), Normal(&get_cursor, "GET_CURSOR")),
         (VarOp(0x11), Normal(&set_text_style, "SET_TEXT_STYLE")),
         (VarOp(0x12), Normal(&buffer_mode, "BUFFER_MODE")),
         (VarOp(0x16), Store(&read_char, "READ_CHAR")),
+        (VarOp(0x17), BranchStore(&scan_table, "SCAN_TABLE")),
     ]
 }
 
@@ -31,7 +36,7 @@ pub fn call_2s(
     store_to: u8,
 ) -> Result<InstructionResult> {
     let address = ops.pull()?.unsigned(state)?;
-    let address = state.memory.unpack_address(address as usize);
+    let address = state.memory.unpack_routine_address(address as usize);
     let arguments = vec![ops.pull()?.unsigned(state)?];
 
     Ok(InstructionResult::Invoke {
@@ -48,7 +53,7 @@ pub fn call_1s(
     store_to: u8,
 ) -> Result<InstructionResult> {
     let address = ops.pull()?.unsigned(state)?;
-    let address = state.memory.unpack_address(address as usize);
+    let address = state.memory.unpack_routine_address(address as usize);
 
     Ok(InstructionResult::Invoke {
         address,
@@ -57,6 +62,25 @@ pub fn call_1s(
     })
 }
 
+/// 0OP:181 (v4) Save the game. Stores 1 if successful, 0 otherwise.
+pub fn save(state: &mut GameState, _: OperandSet, store_to: u8) -> Result<InstructionResult> {
+    let success = state.save()?;
+    state.set_variable(store_to, success as u16);
+    Ok(Continue)
+}
+
+/// 0OP:182 (v4) Restore a saved game. If successful, execution continues from the save
+/// instruction, which stores 2. Otherwise, stores 0.
+pub fn restore(state: &mut GameState, _: OperandSet, store_to: u8) -> Result<InstructionResult> {
+    match state.restore()? {
+        Some(result) => Ok(result),
+        None => {
+            state.set_variable(store_to, 0);
+            Ok(Continue)
+        }
+    }
+}
+
 /// VAR:242 Change the buffer mode.
 pub fn buffer_mode(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let enable_buffering = ops.pull()?.unsigned(state)? != 0;
@@ -71,7 +95,7 @@ pub fn call_vs2(
     store_to: u8,
 ) -> Result<InstructionResult> {
     let address = ops.pull()?.unsigned(state)?;
-    let address = state.memory.unpack_address(address as usize);
+    let address = state.memory.unpack_routine_address(address as usize);
     let arguments: Vec<u16> = ops
         .map(|op| op.try_unsigned(state))
         .collect::<Result<Vec<Option<u16>>>>()?
@@ -96,6 +120,21 @@ pub fn erase_window(state: &mut GameState, mut ops: OperandSet) -> Result<Instru
         x => ClearMode::Single(x as u16),
     };
     state.interface.clear(mode)?;
+    if window == -1 {
+        // Unsplitting the screen also selects the lower window.
+        state.set_window(0)?;
+    }
+    Ok(InstructionResult::Continue)
+}
+
+/// VAR:238 Erase from the cursor to the end of the line, if the value is 1. Any other value
+/// does nothing.
+pub fn erase_line(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let value = ops.pull()?.unsigned(state)?;
+
+    if value == 1 {
+        state.interface.erase_line()?;
+    }
     Ok(InstructionResult::Continue)
 }
 
@@ -112,6 +151,16 @@ pub fn set_cursor(state: &mut GameState, mut ops: OperandSet) -> Result<Instruct
     Ok(InstructionResult::Continue)
 }
 
+/// VAR:240 Write the cursor position (line, then column) to a table.
+pub fn get_cursor(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let table = ops.pull()?.unsigned(state)? as usize;
+
+    let (line, column) = state.interface.get_cursor()?;
+    state.memory.set_word(table, line);
+    state.memory.set_word(table + 2, column);
+    Ok(InstructionResult::Continue)
+}
+
 /// VAR:241 Sets the active text style (bold, emphasis etc.)
 pub fn set_text_style(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let format = ops.pull()?.unsigned(state)?;
@@ -139,7 +188,7 @@ pub fn call_vs(
     store_to: u8,
 ) -> Result<InstructionResult> {
     let address = ops.pull()?.unsigned(state)?;
-    let address = state.memory.unpack_address(address as usize);
+    let address = state.memory.unpack_routine_address(address as usize);
     let arguments: Vec<u16> = ops
         .map(|op| op.try_unsigned(state))
         .collect::<Result<Vec<Option<u16>>>>()?
@@ -154,14 +203,56 @@ pub fn call_vs(
     })
 }
 
-/// VAR:246 Read a single character of input.
+/// VAR:246 Read a single character of input, optionally calling a routine every
+/// `time` tenths of a second while waiting.
 pub fn read_char(
     state: &mut GameState,
-    mut _ops: OperandSet,
+    mut ops: OperandSet,
     store_to: u8,
 ) -> Result<InstructionResult> {
-    let input = state.interface.read_char()?;
-    let zscii = state.memory.zscii_from_code(input)?;
+    // The first operand is always 1 (the keyboard).
+    ops.pull()?;
+    let time = ops.pull()?.try_unsigned(state)?;
+    let routine = ops.pull()?.try_unsigned(state)?;
+
+    let interrupt = state.interrupt(time, routine);
+    let zscii = match state.read_key(interrupt)? {
+        TimedInput::Done(zscii) | TimedInput::Aborted(zscii) => zscii,
+        TimedInput::Halted(result) => return Ok(result),
+    };
     state.set_variable(store_to, zscii.into());
     Ok(InstructionResult::Continue)
 }
+
+/// VAR:247 Search a table for a value, storing the address of the first matching field (or 0)
+/// and branching if one was found. Bit 7 of the form says whether fields are words (set) or
+/// bytes, and bits 0-6 give the length of each field in bytes.
+pub fn scan_table(
+    state: &mut GameState,
+    mut ops: OperandSet,
+    expected: bool,
+    offset: i16,
+    store_to: u8,
+) -> Result<InstructionResult> {
+    let value = ops.pull()?.unsigned(state)?;
+    let table = ops.pull()?.unsigned(state)? as usize;
+    let length = ops.pull()?.unsigned(state)? as usize;
+    let form = ops.pull()?.try_unsigned(state)?.unwrap_or(0x82);
+
+    let field_length = (form & 0x7f) as usize;
+    let words = form & 0x80 != 0;
+    let found = (0..length)
+        .map(|i| table + i * field_length)
+        .find(|&address| {
+            if words {
+                state.memory.get_word(address) == value
+            } else {
+                state.memory.get_byte(address) as u16 == value
+            }
+        });
+
+    state.set_variable(store_to, found.unwrap_or(0) as u16);
+    Ok(state
+        .frame()
+        .conditional_branch(offset, found.is_some(), expected))
+}
@@ -4,10 +4,12 @@ use itertools::Itertools;
 use tracing::warn;
 
 use crate::game::error::GameError;
+use crate::game::instruction::instruction_set::version_gte4;
 use crate::game::instruction::op_code::OpCode;
 use crate::game::instruction::Instruction;
 use crate::game::instruction::{OperandSet, Result as InstructionResult};
-use crate::game::state::GameState;
+use crate::game::state::{GameState, TimedInput};
+use crate::interface::Colour;
 
 pub fn instructions() -> Vec<(OpCode, Instruction)> {
     use crate::game::instruction::instruction_set::common;
@@ -15,6 +17,7 @@ pub fn instructions() -> Vec<(OpCode, Instruction)> {
     use OpCode::*;
     vec![
         (TwoOp(0x1A), Normal(&call_2n, "CALL_2N")),
+        (TwoOp(0x1B), Normal(&set_colour, "SET_COLOUR")),
         (TwoOp(0x1C), Normal(&throw, "THROW")),
         (OneOp(0xF), Normal(&call_1n, "CALL_1N")),
         (ZeroOp(0x9), Store(&catch, "CATCH")),
@@ -24,18 +27,27 @@ pub fn instructions() -> Vec<(OpCode, Instruction)> {
         (VarOp(0x19), Normal(&call_vn, "CALL_VN")),
         (VarOp(0x1A), Normal(&call_vn2, "CALL_VN2")),
         (VarOp(0x1B), Normal(&tokenise, "TOKENISE")),
+        (VarOp(0x1C), Normal(&encode_text, "ENCODE_TEXT")),
+        (VarOp(0x1D), Normal(&copy_table, "COPY_TABLE")),
+        (VarOp(0x1E), Normal(&print_table, "PRINT_TABLE")),
         (VarOp(0x1F), Branch(&check_arg_count, "CHECK_ARG_COUNT")),
+        (Extended(0x0), Store(&save, "SAVE")), // Moved from 0OP:181
+        (Extended(0x1), Store(&restore, "RESTORE")), // Moved from 0OP:182
         (Extended(0x2), Store(&log_shift, "LOG_SHIFT")),
         (Extended(0x3), Store(&art_shift, "ART_SHIFT")),
+        (Extended(0x4), Store(&set_font, "SET_FONT")),
         (Extended(0x9), Store(&save_undo, "SAVE_UNDO")),
         (Extended(0xA), Store(&restore_undo, "RESTORE_UNDO")),
+        (Extended(0xB), Normal(&print_unicode, "PRINT_UNICODE")),
+        (Extended(0xC), Store(&check_unicode, "CHECK_UNICODE")),
+        (Extended(0xD), Normal(&set_true_colour, "SET_TRUE_COLOUR")),
     ]
 }
 
 /// 2OP:26 Execute a routine with 1 argument and throw away the result.
 fn call_2n(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let address = ops.pull()?.unsigned(state)?;
-    let address = state.memory.unpack_address(address as usize);
+    let address = state.memory.unpack_routine_address(address as usize);
 
     let argument = ops.pull()?.unsigned(state)?;
 
@@ -46,6 +58,24 @@ fn call_2n(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResu
     })
 }
 
+/// 2OP:27 Set the foreground and background colours of the current window, from the standard
+/// palette. 0 leaves a colour unchanged, and 1 selects the default.
+fn set_colour(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let foreground = ops.pull()?.unsigned(state)?;
+    let background = ops.pull()?.unsigned(state)?;
+    for colour in [foreground, background] {
+        if colour > 12 {
+            warn!("Unsupported colour {}, ignoring", colour);
+        }
+    }
+
+    state.interface.set_colour(
+        Colour::from_palette(foreground),
+        Colour::from_palette(background),
+    )?;
+    Ok(InstructionResult::Continue)
+}
+
 /// 2OP:28 Return to the specified stack frame, then return.
 fn throw(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let value = ops.pull()?.unsigned(state)?;
@@ -58,7 +88,7 @@ fn throw(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult
 /// 1OP:143 Calls a routine with no arguments and throws away the result.
 fn call_1n(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let address = ops.pull()?.unsigned(state)?;
-    let address = state.memory.unpack_address(address as usize);
+    let address = state.memory.unpack_routine_address(address as usize);
 
     Ok(InstructionResult::Invoke {
         address,
@@ -86,11 +116,17 @@ fn piracy(
         .conditional_branch(offset, is_genuine, expected))
 }
 
-/// VAR:228 Read a string from the user
+/// VAR:228 Read a string from the user, optionally calling a routine every `time` tenths of a
+/// second while waiting. Stores the terminating character, or 0 if the routine ended input.
 fn aread(state: &mut GameState, mut ops: OperandSet, store_to: u8) -> Result<InstructionResult> {
-    // TODO: add time routines
     let text_address = ops.pull()?.unsigned(state)?;
-    let parse_address = ops.pull()?.try_unsigned(state)?;
+    // If the parse buffer is 0, the input isn't tokenised.
+    let parse_address = ops
+        .pull()?
+        .try_unsigned(state)?
+        .filter(|&address| address != 0);
+    let time = ops.pull()?.try_unsigned(state)?;
+    let routine = ops.pull()?.try_unsigned(state)?;
 
     let max_characters = state.memory.get_byte(text_address as usize);
     if max_characters < 3 {
@@ -99,9 +135,16 @@ fn aread(state: &mut GameState, mut ops: OperandSet, store_to: u8) -> Result<Ins
         ));
     }
 
-    let string = state.interface.read_line(max_characters as usize)?;
+    let interrupt = state.interrupt(time, routine);
+    let terminators = state.terminating_keys();
+    let (string, terminator) =
+        match state.read_command(max_characters as usize, &terminators, interrupt)? {
+            TimedInput::Done(input) | TimedInput::Aborted(input) => input,
+            TimedInput::Halted(result) => return Ok(result),
+        };
+    let string = string.to_lowercase();
 
-    state.set_variable(store_to, 13);
+    state.set_variable(store_to, terminator as u16);
     state
         .memory
         .write_input_array(text_address as usize, &string)?;
@@ -113,9 +156,13 @@ fn aread(state: &mut GameState, mut ops: OperandSet, store_to: u8) -> Result<Ins
                 "Parse buffer cannot be less than 6 bytes",
             ));
         }
-        state
-            .memory
-            .parse_string(parse_address as usize, &string, max_words as usize)?;
+        state.memory.parse_string(
+            parse_address as usize,
+            &string,
+            max_words as usize,
+            None,
+            false,
+        )?;
     }
 
     Ok(InstructionResult::Continue)
@@ -124,7 +171,7 @@ fn aread(state: &mut GameState, mut ops: OperandSet, store_to: u8) -> Result<Ins
 /// VAR:249 Call a routine with up to 3 arguments and throw away the result.
 fn call_vn(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let address = ops.pull()?.unsigned(state)?;
-    let address = state.memory.unpack_address(address as usize);
+    let address = state.memory.unpack_routine_address(address as usize);
     let arguments: Vec<u16> = ops
         .map(|op| op.try_unsigned(state))
         .collect::<Result<Vec<Option<u16>>>>()?
@@ -142,7 +189,7 @@ fn call_vn(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResu
 /// VAR:250 Call a routine with up to 7 arguments and throw away the result.
 fn call_vn2(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let address = ops.pull()?.unsigned(state)?;
-    let address = state.memory.unpack_address(address as usize);
+    let address = state.memory.unpack_routine_address(address as usize);
     let arguments: Vec<u16> = ops
         .map(|op| op.try_unsigned(state))
         .collect::<Result<Vec<Option<u16>>>>()?
@@ -157,16 +204,17 @@ fn call_vn2(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionRes
     })
 }
 
-/// VAR:251 Tokenise a string.
+/// VAR:251 Tokenise a string, optionally using another dictionary (and its word separators).
+/// If the flag is set, parse buffer entries for unrecognised words are left untouched, so the
+/// text can be parsed again with several dictionaries.
 fn tokenise(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
     let text_address = ops.pull()?.unsigned(state)?;
     let parse_address = ops.pull()?.unsigned(state)?;
     let dictionary = ops.pull()?.try_unsigned(state)?;
     let flag = ops.pull()?.try_unsigned(state)?;
 
-    if !matches!(flag, None | Some(0)) && !matches!(dictionary, None | Some(0)) {
-        todo!("Implement this");
-    }
+    let dictionary = dictionary.filter(|&d| d != 0).map(usize::from);
+    let skip_unrecognised = !matches!(flag, None | Some(0));
 
     let max_words = state.memory.get_byte(parse_address as usize);
     if max_words < 6 {
@@ -179,10 +227,90 @@ fn tokenise(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionRes
 
     let string = state.memory.read_input_array(t_cursor)?;
 
+    state.memory.parse_string(
+        parse_address as usize,
+        &string,
+        max_words as usize,
+        dictionary,
+        skip_unrecognised,
+    )?;
+
+    Ok(InstructionResult::Continue)
+}
+
+/// VAR:252 Encode a word from a ZSCII text buffer as a dictionary word, writing the result to
+/// another buffer.
+fn encode_text(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let text_address = ops.pull()?.unsigned(state)? as usize;
+    let length = ops.pull()?.unsigned(state)? as usize;
+    let from = ops.pull()?.unsigned(state)? as usize;
+    let coded_address = ops.pull()?.unsigned(state)? as usize;
+
     state
         .memory
-        .parse_string(parse_address as usize, &string, max_words as usize)?;
+        .encode_text(text_address + from, length, coded_address)?;
+    Ok(InstructionResult::Continue)
+}
 
+/// VAR:253 Copy a table. If the second table is 0, the first is zeroed instead. A negative
+/// size forces a forward copy, even if this corrupts overlapping tables.
+fn copy_table(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let first = ops.pull()?.unsigned(state)? as usize;
+    let second = ops.pull()?.unsigned(state)? as usize;
+    let size = ops.pull()?.signed(state)?;
+    let length = size.unsigned_abs() as usize;
+
+    if second == 0 {
+        for i in 0..length {
+            state.memory.set_byte(first + i, 0);
+        }
+    } else if size < 0 || second < first {
+        for i in 0..length {
+            let byte = state.memory.get_byte(first + i);
+            state.memory.set_byte(second + i, byte);
+        }
+    } else {
+        // Copy backwards, so that overlapping tables aren't corrupted.
+        for i in (0..length).rev() {
+            let byte = state.memory.get_byte(first + i);
+            state.memory.set_byte(second + i, byte);
+        }
+    }
+    Ok(InstructionResult::Continue)
+}
+
+/// VAR:254 Print a rectangle of text from a table, `width` characters across and `height`
+/// lines down. `skip` characters are skipped at the end of each line.
+fn print_table(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let table = ops.pull()?.unsigned(state)? as usize;
+    let width = ops.pull()?.unsigned(state)? as usize;
+    let height = ops.pull()?.try_unsigned(state)?.unwrap_or(1);
+    let skip = ops.pull()?.try_unsigned(state)?.unwrap_or(0) as usize;
+
+    let alphabet = state.memory.alphabet();
+    let (line, column) = state.interface.get_cursor()?;
+    for row in 0..height {
+        if row > 0 {
+            // The upper window is a grid, so each line starts directly below the start of the
+            // one before.
+            if state.printing_to_upper_window() {
+                state
+                    .interface
+                    .set_cursor(line.saturating_add(row), column)?;
+            } else {
+                state.print("\n")?;
+            }
+        }
+        let start = table + row as usize * (width + skip);
+        let text = (start..start + width)
+            .filter_map(|address| {
+                alphabet
+                    .decode_zscii(state.memory.get_byte(address).into())
+                    .transpose()
+            })
+            .collect::<Result<String>>()?;
+        state.print(&text)?;
+    }
     Ok(InstructionResult::Continue)
 }
 
@@ -202,6 +330,39 @@ fn check_arg_count(
         .conditional_branch(offset, condition, expected))
 }
 
+/// EXT:0 Save the game or, if a table is given, save the table to an auxiliary file. For a
+/// table, the name is a length byte followed by the file name, and the player is asked to
+/// confirm the name unless prompt is 0. Stores 1 if successful, 0 otherwise.
+fn save(state: &mut GameState, mut ops: OperandSet, store_to: u8) -> Result<InstructionResult> {
+    let table = match ops.pull()?.try_unsigned(state)? {
+        Some(table) => table as usize,
+        None => return version_gte4::save(state, ops, store_to),
+    };
+    let length = ops.pull()?.unsigned(state)? as usize;
+    let name = ops.pull()?.try_unsigned(state)?.map(usize::from);
+    let prompt = ops.pull()?.try_unsigned(state)?.unwrap_or(1) != 0;
+
+    let success = state.save_auxiliary(table, length, name, prompt)?;
+    state.set_variable(store_to, success as u16);
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:1 Restore a saved game or, if a table is given, load an auxiliary file into the table.
+/// The operands are as for save. For a table, stores the number of bytes read.
+fn restore(state: &mut GameState, mut ops: OperandSet, store_to: u8) -> Result<InstructionResult> {
+    let table = match ops.pull()?.try_unsigned(state)? {
+        Some(table) => table as usize,
+        None => return version_gte4::restore(state, ops, store_to),
+    };
+    let length = ops.pull()?.unsigned(state)? as usize;
+    let name = ops.pull()?.try_unsigned(state)?.map(usize::from);
+    let prompt = ops.pull()?.try_unsigned(state)?.unwrap_or(1) != 0;
+
+    let read = state.restore_auxiliary(table, length, name, prompt)?;
+    state.set_variable(store_to, read);
+    Ok(InstructionResult::Continue)
+}
+
 /// EXT:2 Logical shift
 fn log_shift(
     state: &mut GameState,
@@ -250,6 +411,16 @@ fn art_shift(
     Ok(InstructionResult::Continue)
 }
 
+/// EXT:4 Select a font: 1 for the normal font, 3 for character graphics, or 4 for fixed-pitch.
+/// Stores the previous font, or 0 if the font isn't available.
+fn set_font(state: &mut GameState, mut ops: OperandSet, store_to: u8) -> Result<InstructionResult> {
+    let font = ops.pull()?.unsigned(state)?;
+
+    let previous = state.set_font(font);
+    state.set_variable(store_to, previous);
+    Ok(InstructionResult::Continue)
+}
+
 /// EXT:9 Save the current game state to the undo buffer.
 fn save_undo(
     state: &mut GameState,
@@ -266,9 +437,56 @@ fn restore_undo(
     mut _ops: OperandSet,
     store_to: u8,
 ) -> Result<InstructionResult> {
-    let success = state.restore_undo();
+    let success = state.restore_undo()?;
     if !success {
         state.set_variable(store_to, 0);
     }
     Ok(InstructionResult::Continue)
 }
+
+/// EXT:11 Print a Unicode character. Characters the interface can't show are printed as a
+/// question mark.
+fn print_unicode(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let code = ops.pull()?.unsigned(state)?;
+
+    let c = char::from_u32(code.into())
+        .filter(|&c| state.interface.char_support(c).output)
+        .unwrap_or('?');
+    state.print_char(c)?;
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:12 Check whether a Unicode character can be printed (bit 0) and typed (bit 1). Typing a
+/// character also needs it to have a ZSCII code, which means it must be in the translation
+/// table.
+fn check_unicode(
+    state: &mut GameState,
+    mut ops: OperandSet,
+    store_to: u8,
+) -> Result<InstructionResult> {
+    let code = ops.pull()?.unsigned(state)?;
+
+    let result = match char::from_u32(code.into()) {
+        Some(c) => {
+            let support = state.interface.char_support(c);
+            let typeable = support.input && state.memory.alphabet().zscii_from_char(c).is_ok();
+            support.output as u16 | (typeable as u16) << 1
+        }
+        None => 0,
+    };
+    state.set_variable(store_to, result);
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:13 Set the foreground and background colours of the current window, as 15-bit colours.
+/// -1 selects the default colour, and -2 leaves a colour unchanged.
+fn set_true_colour(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let foreground = ops.pull()?.unsigned(state)?;
+    let background = ops.pull()?.unsigned(state)?;
+
+    state.interface.set_colour(
+        Colour::from_true_colour(foreground),
+        Colour::from_true_colour(background),
+    )?;
+    Ok(InstructionResult::Continue)
+}
@@ -0,0 +1,494 @@
+use crate::game::Result;
+use tracing::debug;
+
+use crate::game::error::GameError;
+use crate::game::graphics::{property, FONT_HEIGHT, FONT_WIDTH};
+use crate::game::instruction::op_code::OpCode;
+use crate::game::instruction::Instruction;
+use crate::game::instruction::{OperandSet, Result as InstructionResult};
+use crate::game::state::GameState;
+use crate::interface::{ClearMode, Colour, Font};
+
+pub fn instructions() -> Vec<(OpCode, Instruction)> {
+    use Instruction::*;
+    use OpCode::*;
+    vec![
+        (TwoOp(0x1B), Normal(&set_colour, "SET_COLOUR")),
+        (VarOp(0x9), Store(&pull, "PULL")),
+        (VarOp(0xA), Normal(&split_window, "SPLIT_WINDOW")),
+        (VarOp(0xB), Normal(&set_window, "SET_WINDOW")),
+        (VarOp(0xD), Normal(&erase_window, "ERASE_WINDOW")),
+        (VarOp(0xE), Normal(&erase_line, "ERASE_LINE")),
+        (VarOp(0xF), Normal(&set_cursor, "SET_CURSOR")),
+        (VarOp(0x10), Normal(&get_cursor, "GET_CURSOR")),
+        (Extended(0x4), Store(&set_font, "SET_FONT")),
+        (Extended(0x5), Normal(&draw_picture, "DRAW_PICTURE")),
+        (Extended(0x6), Branch(&picture_data, "PICTURE_DATA")),
+        (Extended(0x7), Normal(&erase_picture, "ERASE_PICTURE")),
+        (Extended(0x8), Normal(&set_margins, "SET_MARGINS")),
+        (Extended(0xD), Normal(&set_true_colour, "SET_TRUE_COLOUR")),
+        (Extended(0x10), Normal(&move_window, "MOVE_WINDOW")),
+        (Extended(0x11), Normal(&window_size, "WINDOW_SIZE")),
+        (Extended(0x12), Normal(&window_style, "WINDOW_STYLE")),
+        (Extended(0x13), Store(&get_wind_prop, "GET_WIND_PROP")),
+        (Extended(0x14), Normal(&scroll_window, "SCROLL_WINDOW")),
+        (Extended(0x15), Normal(&pop_stack, "POP_STACK")),
+        (Extended(0x16), Normal(&read_mouse, "READ_MOUSE")),
+        (Extended(0x17), Normal(&mouse_window, "MOUSE_WINDOW")),
+        (Extended(0x18), Branch(&push_stack, "PUSH_STACK")),
+        (Extended(0x19), Normal(&put_wind_prop, "PUT_WIND_PROP")),
+        (Extended(0x1C), Normal(&picture_table, "PICTURE_TABLE")),
+        (Extended(0x1D), Store(&buffer_screen, "BUFFER_SCREEN")),
+    ]
+}
+
+/// Work out which window an operand refers to. -3 (or an omitted operand) is the current window.
+fn window(state: &GameState, value: Option<u16>) -> Result<u16> {
+    match value.map(|value| value as i16) {
+        None | Some(-3) => Ok(state.active_window()),
+        Some(window @ 0..=7) => Ok(window as u16),
+        Some(window) => Err(GameError::invalid_operation(format!(
+            "Invalid window {}",
+            window
+        ))),
+    }
+}
+
+/// Show the screen straight away, unless screen updates are buffered.
+fn update(state: &mut GameState) -> Result<()> {
+    if !state.graphics()?.buffered() {
+        state.present_screen()?;
+    }
+    Ok(())
+}
+
+/// 2OP:27 Set the foreground and background colours of a window (the current window by
+/// default), from the standard palette.
+fn set_colour(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let foreground = Colour::from_palette(ops.pull()?.unsigned(state)?);
+    let background = Colour::from_palette(ops.pull()?.unsigned(state)?);
+    let window = ops.pull()?.try_unsigned(state)?;
+    let window = self::window(state, window)?;
+
+    state
+        .graphics()?
+        .set_colour(window, foreground, background)?;
+    if window == state.active_window() {
+        state.interface.set_colour(foreground, background)?;
+    }
+    Ok(InstructionResult::Continue)
+}
+
+/// VAR:233 Pull a value off the game stack, or off a user stack if one is given, and store it.
+fn pull(state: &mut GameState, mut ops: OperandSet, store_to: u8) -> Result<InstructionResult> {
+    let value = match ops.pull()?.try_unsigned(state)? {
+        Some(stack) => {
+            let stack = stack as usize;
+            let free = state.memory.get_word(stack) + 1;
+            state.memory.set_word(stack, free);
+            state.memory.get_word(stack + 2 * free as usize)
+        }
+        None => state.frame().pop_stack()?,
+    };
+    state.set_variable(store_to, value);
+    Ok(InstructionResult::Continue)
+}
+
+/// VAR:234 Split the screen so that the upper window is the given number of pixels high.
+fn split_window(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let height = ops.pull()?.unsigned(state)?;
+
+    state.graphics()?.split(height)?;
+    state.interface.split_screen(height.div_ceil(FONT_HEIGHT))?;
+    Ok(InstructionResult::Continue)
+}
+
+/// VAR:235 Make the given window the active window. -3 is the current window.
+fn set_window(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let window = ops.pull()?.unsigned(state)?;
+    let window = self::window(state, Some(window))?;
+
+    state.set_window(window)?;
+    Ok(InstructionResult::Continue)
+}
+
+/// VAR:237 Clear a window, or the whole screen. -1 also unsplits the screen, and -2 leaves the
+/// windows as they are.
+fn erase_window(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let window = ops.pull()?.signed(state)?;
+
+    match window {
+        -1 | -2 => {
+            if window == -1 {
+                state.graphics()?.split(0)?;
+            }
+            let current = state.active_window();
+            state.graphics()?.clear_screen(current);
+            state.interface.clear(if window == -1 {
+                ClearMode::FullUnsplit
+            } else {
+                ClearMode::Full
+            })?;
+            if window == -1 {
+                state.set_window(0)?;
+            }
+        }
+        _ => {
+            let window = self::window(state, Some(window as u16))?;
+            state.graphics()?.erase_window(window)?;
+            state.interface.clear(ClearMode::Single(window.min(1)))?;
+        }
+    }
+    update(state)?;
+    Ok(InstructionResult::Continue)
+}
+
+/// VAR:238 Erase the current window from the cursor to the end of the line (for a value of 1),
+/// or for the given number of pixels.
+fn erase_line(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let value = ops.pull()?.unsigned(state)?;
+
+    let window = state.active_window();
+    let pixels = (value != 1).then_some(value);
+    state.graphics()?.erase_line(window, pixels)?;
+    if value == 1 {
+        state.interface.erase_line()?;
+    }
+    update(state)?;
+    Ok(InstructionResult::Continue)
+}
+
+/// VAR:239 Move the cursor of a window (the current window by default), in pixels. A line of -1
+/// hides the cursor, and -2 shows it again.
+fn set_cursor(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let y = ops.pull()?.signed(state)?;
+    let x = ops.pull()?.unsigned(state)?;
+    let window = ops.pull()?.try_unsigned(state)?;
+    let window = self::window(state, window)?;
+    if y < 0 {
+        return Ok(InstructionResult::Continue);
+    }
+
+    state.graphics()?.set_cursor(window, y as u16, x)?;
+    if window != 0 && window == state.active_window() {
+        state.interface.set_cursor(
+            (y as u16 - 1) / FONT_HEIGHT + 1,
+            x.saturating_sub(1) / FONT_WIDTH + 1,
+        )?;
+    }
+    Ok(InstructionResult::Continue)
+}
+
+/// VAR:240 Write the cursor position of the current window (y, then x, in pixels) to a table.
+fn get_cursor(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let table = ops.pull()?.unsigned(state)? as usize;
+
+    let window = state.active_window();
+    let graphics = state.graphics()?;
+    let y = graphics.property(window, property::Y_CURSOR as u16)?;
+    let x = graphics.property(window, property::X_CURSOR as u16)?;
+    state.memory.set_word(table, y);
+    state.memory.set_word(table + 2, x);
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:4 Select the font of a window (the current window by default). Stores the window's
+/// previous font, or 0 if the font isn't available.
+fn set_font(state: &mut GameState, mut ops: OperandSet, store_to: u8) -> Result<InstructionResult> {
+    let font = ops.pull()?.unsigned(state)?;
+    let window = ops.pull()?.try_unsigned(state)?;
+    let window = self::window(state, window)?;
+
+    let previous = state
+        .graphics()?
+        .property(window, property::FONT_NUMBER as u16)?;
+    let available = Font::from_number(font).is_some_and(|font| font.available());
+    let result = if font == 0 {
+        previous
+    } else if available {
+        state
+            .graphics()?
+            .set_property(window, property::FONT_NUMBER as u16, font)?;
+        if window == state.active_window() {
+            state.set_font(font);
+        }
+        previous
+    } else {
+        0
+    };
+    state.set_variable(store_to, result);
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:5 Draw a picture in the current window, at the given position or at the cursor.
+fn draw_picture(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let picture = ops.pull()?.unsigned(state)?;
+    let y = ops.pull()?.try_unsigned(state)?;
+    let x = ops.pull()?.try_unsigned(state)?;
+
+    let window = state.active_window();
+    state.graphics()?.draw_picture(window, picture, y, x)?;
+    update(state)?;
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:6 Write the height and width of a picture into a table, and branch if the picture exists.
+/// For picture 0, write the number of pictures and the release number of the picture file.
+fn picture_data(
+    state: &mut GameState,
+    mut ops: OperandSet,
+    expected: bool,
+    offset: i16,
+) -> Result<InstructionResult> {
+    let picture = ops.pull()?.unsigned(state)?;
+    let table = ops.pull()?.unsigned(state)? as usize;
+
+    let graphics = state.graphics()?;
+    let data = match picture {
+        0 => {
+            let count = graphics.picture_count();
+            (count > 0).then_some((count, 0))
+        }
+        _ => graphics
+            .picture(picture)
+            .map(|image| (image.height as u16, image.width as u16)),
+    };
+    if let Some((first, second)) = data {
+        state.memory.set_word(table, first);
+        state.memory.set_word(table + 2, second);
+    }
+    Ok(state
+        .frame()
+        .conditional_branch(offset, data.is_some(), expected))
+}
+
+/// EXT:7 Clear the area a picture would cover to the background colour.
+fn erase_picture(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let picture = ops.pull()?.unsigned(state)?;
+    let y = ops.pull()?.try_unsigned(state)?;
+    let x = ops.pull()?.try_unsigned(state)?;
+
+    let window = state.active_window();
+    state.graphics()?.erase_picture(window, picture, y, x)?;
+    update(state)?;
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:8 Set the left and right margins of a window (the current window by default), in pixels.
+fn set_margins(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let left = ops.pull()?.unsigned(state)?;
+    let right = ops.pull()?.unsigned(state)?;
+    let window = ops.pull()?.try_unsigned(state)?;
+    let window = self::window(state, window)?;
+
+    let graphics = state.graphics()?;
+    graphics.set_property(window, property::LEFT_MARGIN as u16, left)?;
+    graphics.set_property(window, property::RIGHT_MARGIN as u16, right)?;
+    // Keep the cursor inside the margins.
+    let x = graphics.property(window, property::X_CURSOR as u16)?;
+    if x <= left {
+        graphics.set_property(window, property::X_CURSOR as u16, left + 1)?;
+    }
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:13 Set the foreground and background colours of a window (the current window by
+/// default), as 15-bit colours.
+fn set_true_colour(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let foreground = Colour::from_true_colour(ops.pull()?.unsigned(state)?);
+    let background = Colour::from_true_colour(ops.pull()?.unsigned(state)?);
+    let window = ops.pull()?.try_unsigned(state)?;
+    let window = self::window(state, window)?;
+
+    state
+        .graphics()?
+        .set_colour(window, foreground, background)?;
+    if window == state.active_window() {
+        state.interface.set_colour(foreground, background)?;
+    }
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:16 Move a window so that its top left corner is at the given position, in pixels.
+fn move_window(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let window = ops.pull()?.unsigned(state)?;
+    let window = self::window(state, Some(window))?;
+    let y = ops.pull()?.unsigned(state)?;
+    let x = ops.pull()?.unsigned(state)?;
+
+    let graphics = state.graphics()?;
+    graphics.set_property(window, property::Y as u16, y)?;
+    graphics.set_property(window, property::X as u16, x)?;
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:17 Change the size of a window, in pixels.
+fn window_size(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let window = ops.pull()?.unsigned(state)?;
+    let window = self::window(state, Some(window))?;
+    let height = ops.pull()?.unsigned(state)?;
+    let width = ops.pull()?.unsigned(state)?;
+
+    let graphics = state.graphics()?;
+    graphics.set_property(window, property::Y_SIZE as u16, height)?;
+    graphics.set_property(window, property::X_SIZE as u16, width)?;
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:18 Change the attributes of a window. The operation sets the attributes to the flags
+/// (0), sets the flags (1), clears them (2) or toggles them (3).
+fn window_style(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let window = ops.pull()?.unsigned(state)?;
+    let window = self::window(state, Some(window))?;
+    let flags = ops.pull()?.unsigned(state)?;
+    let operation = ops.pull()?.try_unsigned(state)?.unwrap_or(0);
+
+    let graphics = state.graphics()?;
+    let attributes = graphics.property(window, property::ATTRIBUTES as u16)?;
+    let attributes = match operation {
+        0 => flags,
+        1 => attributes | flags,
+        2 => attributes & !flags,
+        3 => attributes ^ flags,
+        _ => {
+            return Err(GameError::invalid_operation(format!(
+                "Invalid window style operation {}",
+                operation
+            )))
+        }
+    };
+    graphics.set_property(window, property::ATTRIBUTES as u16, attributes)?;
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:19 Read a window property.
+fn get_wind_prop(
+    state: &mut GameState,
+    mut ops: OperandSet,
+    store_to: u8,
+) -> Result<InstructionResult> {
+    let window = ops.pull()?.unsigned(state)?;
+    let window = self::window(state, Some(window))?;
+    let property = ops.pull()?.unsigned(state)?;
+
+    let value = state.graphics()?.property(window, property)?;
+    state.set_variable(store_to, value);
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:20 Scroll a window up by a number of pixels, or down if the number is negative.
+fn scroll_window(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let window = ops.pull()?.unsigned(state)?;
+    let window = self::window(state, Some(window))?;
+    let pixels = ops.pull()?.signed(state)?;
+
+    state.graphics()?.scroll(window, pixels)?;
+    update(state)?;
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:21 Throw away a number of values from the game stack, or from a user stack if one is
+/// given. The first word of a user stack holds the number of free slots left in it.
+fn pop_stack(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let items = ops.pull()?.unsigned(state)?;
+
+    match ops.pull()?.try_unsigned(state)? {
+        Some(stack) => {
+            let stack = stack as usize;
+            let free = state.memory.get_word(stack);
+            state.memory.set_word(stack, free + items);
+        }
+        None => {
+            for _ in 0..items {
+                state.frame().pop_stack()?;
+            }
+        }
+    }
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:22 Write the mouse position, buttons and menu selection to a table. The position is
+/// that of the last click, since the interfaces only report the mouse when it's clicked, and
+/// there are no menus.
+fn read_mouse(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let table = ops.pull()?.unsigned(state)? as usize;
+
+    let (x, y) = state.mouse_position();
+    let buttons = state.mouse_buttons();
+    for (index, value) in [y, x, buttons, 0].into_iter().enumerate() {
+        state.memory.set_word(table + 2 * index, value);
+    }
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:23 Restrict the mouse to a window, or -1 for the whole screen.
+fn mouse_window(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let window = ops.pull()?.unsigned(state)?;
+    let window = match window as i16 {
+        -1 => window,
+        _ => self::window(state, Some(window))?,
+    };
+
+    state.graphics()?.set_mouse_window(window);
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:24 Push a value onto a user stack, and branch if there was room for it.
+fn push_stack(
+    state: &mut GameState,
+    mut ops: OperandSet,
+    expected: bool,
+    offset: i16,
+) -> Result<InstructionResult> {
+    let value = ops.pull()?.unsigned(state)?;
+    let stack = ops.pull()?.unsigned(state)? as usize;
+
+    let free = state.memory.get_word(stack);
+    if free > 0 {
+        state.memory.set_word(stack + 2 * free as usize, value);
+        state.memory.set_word(stack, free - 1);
+    }
+    Ok(state.frame().conditional_branch(offset, free > 0, expected))
+}
+
+/// EXT:25 Set a window property.
+fn put_wind_prop(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let window = ops.pull()?.unsigned(state)?;
+    let window = self::window(state, Some(window))?;
+    let property = ops.pull()?.unsigned(state)?;
+    let value = ops.pull()?.unsigned(state)?;
+
+    state.graphics()?.set_property(window, property, value)?;
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:28 Warn the interpreter which pictures are about to be drawn. Pictures are loaded when
+/// they're first drawn, so this does nothing.
+fn picture_table(state: &mut GameState, mut ops: OperandSet) -> Result<InstructionResult> {
+    let table = ops.pull()?.unsigned(state)?;
+    debug!("Ignoring picture table at {:x}", table);
+    Ok(InstructionResult::Continue)
+}
+
+/// EXT:29 Turn screen buffering on (0) or off (1), or show the screen now (-1). Stores the
+/// previous setting.
+fn buffer_screen(
+    state: &mut GameState,
+    mut ops: OperandSet,
+    store_to: u8,
+) -> Result<InstructionResult> {
+    let mode = ops.pull()?.signed(state)?;
+
+    let graphics = state.graphics()?;
+    let previous = match mode {
+        -1 => {
+            let buffered = graphics.buffered();
+            state.present_screen()?;
+            buffered
+        }
+        mode => graphics.set_buffered(mode == 0),
+    };
+    state.set_variable(store_to, if previous { 0 } else { 1 });
+    update(state)?;
+    Ok(InstructionResult::Continue)
+}
@@ -1,4 +1,5 @@
 use std::char;
+use std::cmp::Ordering;
 use std::convert::TryInto;
 use std::iter::successors;
 
@@ -33,6 +34,11 @@ impl Memory {
         self.data[address]
     }
 
+    /// The size of the memory, in bytes.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
     /// Return a series of bytes from the memory.
     pub fn get_bytes(&self, start: usize, length: usize) -> Vec<u8> {
         self.data[start..start + length].to_vec()
@@ -115,8 +121,20 @@ impl Memory {
         self.get_byte(address::VERSION)
     }
 
+    /// Return the story's release number.
+    pub fn release_number(&self) -> u16 {
+        self.get_word(address::RELEASE_NUMBER)
+    }
+
+    /// Return the story's serial number (usually its compilation date as YYMMDD).
+    pub fn serial_number(&self) -> [u8; 6] {
+        self.data[address::SERIAL_NUMBER..address::SERIAL_NUMBER + 6]
+            .try_into()
+            .unwrap()
+    }
+
     /// Return the expected result of the checksum operation.
-    fn checksum(&self) -> u16 {
+    pub fn checksum(&self) -> u16 {
         self.get_word(address::CHECKSUM)
     }
 
@@ -135,6 +153,22 @@ impl Memory {
         self.get_word(address::STATIC_MEMORY_BASE)
     }
 
+    /// Return the dynamic section of memory (everything below static memory).
+    pub fn dynamic_memory(&self) -> &[u8] {
+        &self.data[..self.static_memory_base() as usize]
+    }
+
+    /// Overwrite the dynamic section of memory.
+    pub fn set_dynamic_memory(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() != self.static_memory_base() as usize {
+            return Err(GameError::invalid_operation(
+                "Dynamic memory is the wrong length",
+            ));
+        }
+        self.set_bytes(0, data);
+        Ok(())
+    }
+
     /// Return the location of the abbreviation table.
     fn abbreviation_table_location(&self) -> u16 {
         self.get_word(address::ABBREVIATION_TABLE_LOCATION)
@@ -172,13 +206,27 @@ impl Memory {
         self.get_word(address::HEADER_EXTENSION_TABLE_LOCATION)
     }
 
+    /// Set an entry in the header extension table, if the story's table is long enough to hold it.
+    fn set_header_extension_word(&mut self, entry: usize, value: u16) {
+        let extension_table = self.header_extension_table_location() as usize;
+        if extension_table != 0 && self.get_word(extension_table) as usize >= entry {
+            self.set_word(extension_table + (2 * entry), value);
+        }
+    }
+
     /// Returns the story's unicode translation table, or None if the default table
     /// should be used.
     fn unicode_translation_table(&self) -> Option<Vec<char>> {
-        match self.get_word(
-            self.header_extension_table_location() as usize
-                + (2 * address::UNICODE_TRANSLATION_TABLE_LOCATION),
-        ) {
+        let extension_table = self.header_extension_table_location() as usize;
+        // Early stories have no header extension table, or one too short to hold this entry.
+        // The first word of the table is the number of entries that follow it.
+        if extension_table == 0
+            || (self.get_word(extension_table) as usize)
+                < address::UNICODE_TRANSLATION_TABLE_LOCATION
+        {
+            return None;
+        }
+        match self.get_word(extension_table + (2 * address::UNICODE_TRANSLATION_TABLE_LOCATION)) {
             0 => None,
             addr => {
                 let mut cursor = addr as usize;
@@ -240,29 +288,44 @@ impl Memory {
         }
     }
 
-    /// Decompress a packed address.
-    pub fn unpack_address(&self, address: usize) -> usize {
+    /// Decompress a packed routine address.
+    pub fn unpack_routine_address(&self, address: usize) -> usize {
+        self.unpack_address(address, address::ROUTINES_OFFSET)
+    }
+
+    /// Decompress a packed string address.
+    pub fn unpack_string_address(&self, address: usize) -> usize {
+        self.unpack_address(address, address::STRINGS_OFFSET)
+    }
+
+    /// Decompress a packed address. Versions 6 and 7 add an offset from the header, which is
+    /// different for routines and strings.
+    fn unpack_address(&self, address: usize, offset_location: usize) -> usize {
         match self.version() {
             1..=3 => 2 * address,
             4..=5 => 4 * address,
-            8 => 8 * address,
-            _ => panic!("Implement me"), //TODO: Implement this
+            6..=7 => 4 * address + 8 * self.get_word(offset_location) as usize,
+            _ => 8 * address,
         }
     }
 
-    fn flag(&self, mut address: usize, mut bit: u16) -> bool {
-        if bit >= 8 {
-            address += 1;
-            bit -= 8;
+    /// Find the byte holding a header flag. Flags 2 is a word, so its bits are numbered from the
+    /// least significant bit of its second byte.
+    fn flag_location(address: usize, bit: u16) -> (usize, u16) {
+        if address == address::FLAGS_2 {
+            (address + 1 - bit as usize / 8, bit % 8)
+        } else {
+            (address, bit)
         }
+    }
+
+    fn flag(&self, address: usize, bit: u16) -> bool {
+        let (address, bit) = Self::flag_location(address, bit);
         (self.data[address] & (1 << bit)) != 0
     }
 
-    fn set_flag(&mut self, mut address: usize, mut bit: u16, set: bool) {
-        if bit >= 8 {
-            address += 1;
-            bit -= 8;
-        }
+    fn set_flag(&mut self, address: usize, bit: u16, set: bool) {
+        let (address, bit) = Self::flag_location(address, bit);
         if set {
             self.data[address] |= 1 << bit;
         } else {
@@ -278,6 +341,15 @@ impl Memory {
         self.set_flag(address::FLAGS_2, address::flags2::TRANSCRIPTING_ON, value);
     }
 
+    /// Whether the status line shows the time (hours and minutes) rather than the score and
+    /// number of moves. Only used before version 4.
+    pub fn status_line_shows_time(&self) -> bool {
+        self.flag(
+            address::FLAGS_1,
+            address::flags1_bits_pre_v4::STATUS_LINE_TYPE,
+        )
+    }
+
     pub fn force_fixed_font(&self) -> bool {
         self.flag(address::FLAGS_2, address::flags2::FORCE_FIXED_PITCH)
     }
@@ -290,7 +362,7 @@ impl Memory {
     pub fn set_general_headers(&mut self) {
         if self.version() < 4 {
             use address::flags1_bits_pre_v4::*;
-            self.set_flag(address::FLAGS_1, STATUS_LINE_UNAVAILABLE, true);
+            self.set_flag(address::FLAGS_1, STATUS_LINE_UNAVAILABLE, false);
             self.set_flag(address::FLAGS_1, SCREEN_SPLITTING_AVAILABLE, true);
             self.set_flag(address::FLAGS_1, VARIABLE_PITCH_FONT_DEFAULT, true);
         } else {
@@ -301,16 +373,23 @@ impl Memory {
             self.set_flag(address::FLAGS_1, ITALICS_AVAILABLE, true);
             self.set_flag(address::FLAGS_1, FIXED_WIDTH_AVAILABLE, true);
             self.set_flag(address::FLAGS_1, SOUND_EFFECTS_AVAILABLE, false);
-            self.set_flag(address::FLAGS_1, TIMED_INPUT_AVAILABLE, false);
+            self.set_flag(address::FLAGS_1, TIMED_INPUT_AVAILABLE, true);
         }
         use address::flags2::*;
         self.set_flag(address::FLAGS_2, TRANSCRIPTING_ON, false);
         self.set_flag(address::FLAGS_2, PICTURE_SUPPORT, false);
         self.set_flag(address::FLAGS_2, UNDO_SUPPORT, true);
-        self.set_flag(address::FLAGS_2, MOUSE_SUPPORT, false);
-        self.set_flag(address::FLAGS_2, COLOR_SUPPORT, false);
         self.set_flag(address::FLAGS_2, SOUND_EFFECT_SUPPORT, false);
-        self.set_flag(address::FLAGS_2, MENU_SUPPORT, false)
+        self.set_flag(address::FLAGS_2, MENU_SUPPORT, false);
+
+        if self.version() >= 5 {
+            // The interfaces draw white text on a black background, in both the palette and
+            // true colour.
+            self.set_byte(address::DEFAULT_BACKGROUND_COLOUR, 2);
+            self.set_byte(address::DEFAULT_FOREGROUND_COLOUR, 9);
+            self.set_header_extension_word(address::TRUE_DEFAULT_FOREGROUND_COLOUR, 0x7FFF);
+            self.set_header_extension_word(address::TRUE_DEFAULT_BACKGROUND_COLOUR, 0x0000);
+        }
     }
 
     /// Set the screen size headers
@@ -328,6 +407,81 @@ impl Memory {
         }
     }
 
+    /// Set the font size headers, in screen units. Version 6 stores the height first.
+    pub fn set_font_size(&mut self, width: u8, height: u8) {
+        match self.version() {
+            6 => {
+                self.set_byte(address::FONT_WIDTH, height);
+                self.set_byte(address::FONT_HEIGHT, width);
+            }
+            5.. => {
+                self.set_byte(address::FONT_WIDTH, width);
+                self.set_byte(address::FONT_HEIGHT, height);
+            }
+            _ => {}
+        }
+    }
+
+    /// Set whether the interpreter can draw pictures (in version 6).
+    pub fn set_pictures_available(&mut self, available: bool) {
+        use address::flags1_bits_post_v4::PICTURE_DISPLAYING_AVAILABLE;
+        self.set_flag(address::FLAGS_1, PICTURE_DISPLAYING_AVAILABLE, available);
+        self.set_flag(
+            address::FLAGS_2,
+            address::flags2::PICTURE_SUPPORT,
+            available,
+        );
+    }
+
+    /// Set whether the interpreter can play sound effects. Before version 4 there's no bit for
+    /// this in the first flags byte, and the game uses the one in the second to ask for sounds.
+    pub fn set_sounds_available(&mut self, available: bool) {
+        if self.version() >= 4 {
+            use address::flags1_bits_post_v4::SOUND_EFFECTS_AVAILABLE;
+            self.set_flag(address::FLAGS_1, SOUND_EFFECTS_AVAILABLE, available);
+        }
+        self.set_flag(
+            address::FLAGS_2,
+            address::flags2::SOUND_EFFECT_SUPPORT,
+            available,
+        );
+    }
+
+    /// Clear the game's request for mouse input if there's no mouse to give it.
+    pub fn set_mouse_available(&mut self, available: bool) {
+        if !available {
+            self.set_flag(address::FLAGS_2, address::flags2::MOUSE_SUPPORT, false);
+        }
+    }
+
+    /// Tell the game where the mouse was last clicked, in screen units from the top left (1, 1).
+    pub fn set_mouse_position(&mut self, x: u16, y: u16) {
+        self.set_header_extension_word(address::MOUSE_CLICK_COORDS_X, x);
+        self.set_header_extension_word(address::MOUSE_CLICK_COORDS_Y, y);
+    }
+
+    /// The ZSCII codes of the keys that end a line of input as well as Enter (in version 5 and
+    /// later). 255 stands for every key that could.
+    pub fn terminating_characters(&self) -> Vec<u8> {
+        if self.version() < 5 {
+            return Vec::new();
+        }
+        let table = self.get_word(address::TERMINATING_CHARACTER_TABLE_LOCATION) as usize;
+        if table == 0 {
+            return Vec::new();
+        }
+        self.data[table.min(self.data.len())..]
+            .iter()
+            .take_while(|&&zscii| zscii != 0)
+            .copied()
+            .collect()
+    }
+
+    /// Set whether the interpreter can undo (with `save_undo` and `restore_undo`).
+    pub fn set_undo_available(&mut self, available: bool) {
+        self.set_flag(address::FLAGS_2, address::flags2::UNDO_SUPPORT, available);
+    }
+
     /// Extract an encoded Z-Character character sequence from the memory.
     pub fn character_sequence(&self, mut cursor: usize) -> Vec<u8> {
         let mut z_chars = Vec::new();
@@ -385,6 +539,24 @@ impl Memory {
             + ((object_id - 1) * self.object_entry_length())
     }
 
+    /// The number of objects. The object table doesn't record this, but the property tables
+    /// conventionally follow it, so objects are counted up to the first property table.
+    pub fn object_count(&self) -> u16 {
+        let first = self.object_location(1) as usize;
+        let entry_length = self.object_entry_length() as usize;
+        let max = match self.version() {
+            1..=3 => 255,
+            _ => u16::MAX,
+        };
+        let mut end = self.data.len();
+        let mut count = 0;
+        while count < max && first + (count as usize + 1) * entry_length <= end {
+            count += 1;
+            end = end.min(self.object_properties_table_location(count) as usize);
+        }
+        count
+    }
+
     pub fn object_attribute(&self, object_id: u16, attribute: u16) -> bool {
         let location = self.object_location(object_id) as usize;
         let offset = attribute as usize / 8;
@@ -580,9 +752,10 @@ impl Memory {
             .nth(1)
     }
 
-    pub fn word_separators(&self) -> Result<Vec<char>> {
+    /// Returns the word separators listed at the start of a dictionary.
+    pub fn word_separators(&self, dictionary: usize) -> Result<Vec<char>> {
         let alphabet = self.alphabet();
-        let mut cursor = self.dictionary_location();
+        let mut cursor = dictionary;
         let count = self.read_byte(&mut cursor);
         let mut result = Vec::new();
         for _ in 0..count {
@@ -594,22 +767,109 @@ impl Memory {
         Ok(result)
     }
 
-    fn dictionary(&self) -> Result<Vec<(usize, String)>> {
-        let mut cursor = self.dictionary_location();
+    /// Find a word in a dictionary, returning the address of its entry, or 0 if it's missing.
+    /// Words are compared by their encoded form, so only the start of a long word matters.
+    /// Dictionaries are sorted by encoded form, unless the entry count is negative.
+    pub fn dictionary_lookup(&self, dictionary: usize, word: &str) -> usize {
+        let key = self.encode_word(word);
+        let mut cursor = dictionary;
         let separator_count = self.read_byte(&mut cursor) as usize;
         cursor += separator_count;
 
         let entry_length = self.read_byte(&mut cursor) as usize;
-        let entry_count = self.read_word(&mut cursor) as usize;
+        let entry_count = self.read_word(&mut cursor) as i16;
+        let entry_key = |i: usize| {
+            let address = cursor + (i * entry_length);
+            &self.data[address..address + key.len()]
+        };
 
-        let mut result = Vec::new();
+        if entry_count < 0 {
+            return (0..entry_count.unsigned_abs() as usize)
+                .find(|&i| entry_key(i) == key)
+                .map_or(0, |i| cursor + (i * entry_length));
+        }
 
-        for i in 0..entry_count {
-            let address = cursor + (i * entry_length);
-            let text = self.extract_string(address, true)?.0;
-            result.push((address, text));
+        // Encoded words are sequences of big-endian words, so comparing their bytes gives
+        // the same order as comparing the words numerically.
+        let (mut low, mut high) = (0, entry_count as usize);
+        while low < high {
+            let middle = (low + high) / 2;
+            match entry_key(middle).cmp(&key) {
+                Ordering::Less => low = middle + 1,
+                Ordering::Greater => high = middle,
+                Ordering::Equal => return cursor + (middle * entry_length),
+            }
         }
-        Ok(result)
+        0
+    }
+
+    /// Encode text as a dictionary word: a Z-string truncated or padded to exactly 6
+    /// Z-characters (9 from version 4). Characters missing from the alphabets are written as
+    /// ZSCII escapes.
+    pub fn encode_word(&self, text: &str) -> Vec<u8> {
+        let alphabet = self.alphabet();
+        let length = if self.version() <= 3 { 6 } else { 9 };
+        // Versions 1 and 2 use the single shift characters; later versions only have those.
+        let (shift_a1, shift_a2) = if self.version() <= 2 { (2, 3) } else { (4, 5) };
+
+        let mut zchars = Vec::with_capacity(length + 3);
+        for c in text.chars() {
+            if zchars.len() >= length {
+                break;
+            }
+            if c == ' ' {
+                zchars.push(0);
+                continue;
+            }
+            match alphabet.encode_zchar(c) {
+                Some((zchar, AlphabetTable::A0)) => zchars.push(zchar),
+                Some((zchar, AlphabetTable::A1)) => zchars.extend([shift_a1, zchar]),
+                Some((zchar, AlphabetTable::A2)) => zchars.extend([shift_a2, zchar]),
+                None => {
+                    let zscii = alphabet.zscii_from_char(c).unwrap_or(b'?');
+                    zchars.extend([shift_a2, 6, zscii >> 5, zscii & 0b11111]);
+                }
+            }
+        }
+        // A multi-character sequence cut off at the end is left incomplete, as in Infocom's
+        // own dictionaries.
+        zchars.resize(length, 5);
+
+        let mut bytes = Vec::with_capacity(length / 3 * 2);
+        for (i, chunk) in zchars.chunks(3).enumerate() {
+            let mut word = ((chunk[0] as u16) << 10) | ((chunk[1] as u16) << 5) | (chunk[2] as u16);
+            if i == length / 3 - 1 {
+                word |= 0x8000;
+            }
+            bytes.extend(word.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Encode `length` characters of ZSCII text as a dictionary word, writing it to `coded`,
+    /// which must be in dynamic memory.
+    pub fn encode_text(&mut self, text: usize, length: usize, coded: usize) -> Result<()> {
+        if text + length > self.size() {
+            return Err(GameError::invalid_operation(
+                "Text to encode runs past the end of memory",
+            ));
+        }
+        let alphabet = self.alphabet();
+        let mut word = String::with_capacity(length);
+        for &zscii in &self.data[text..text + length] {
+            if let Some(c) = alphabet.decode_zscii(zscii.into())? {
+                word.push(c);
+            }
+        }
+
+        let encoded = self.encode_word(&word);
+        if coded + encoded.len() > self.dynamic_memory().len() {
+            return Err(GameError::invalid_operation(
+                "Encoded text cannot be written outside dynamic memory",
+            ));
+        }
+        self.set_bytes(coded, &encoded);
+        Ok(())
     }
 
     /// Retrieve the location of an abbreviation from the abbreviation tables(s)
@@ -651,8 +911,9 @@ impl Memory {
         let mut output = String::new();
         let alphabet = self.alphabet();
         let version = self.version();
+        // Skip the byte giving the maximum number of characters.
+        start += 1;
         if version >= 5 {
-            start += 1;
             let num_chars = self.read_byte(&mut start);
             for _ in 0..num_chars {
                 let b = self.read_byte(&mut start);
@@ -674,9 +935,9 @@ impl Memory {
 
     pub fn write_input_array(&mut self, mut start: usize, text: &str) -> Result<()> {
         let alphabet = self.alphabet();
+        // Advance past the 'expected number of input characters'
+        start += 1;
         if self.version() >= 5 {
-            // Advance past the 'expected number of input characters'
-            start += 1;
             let existing = self.get_byte(start) as i8;
             if existing > 0 {
                 start += existing as usize;
@@ -684,7 +945,8 @@ impl Memory {
             self.write_byte(&mut start, text.chars().count() as u8);
         }
         for c in text.chars() {
-            self.write_byte(&mut start, alphabet.zscii_from_char(c)?);
+            // Characters missing from the translation table can't reach the game.
+            self.write_byte(&mut start, alphabet.zscii_from_char(c).unwrap_or(b'?'));
         }
         if self.version() < 5 {
             self.write_byte(&mut start, 0);
@@ -692,6 +954,26 @@ impl Memory {
         Ok(())
     }
 
+    /// Append text to an output stream 3 table, updating the length word at the start of the
+    /// table.
+    pub fn write_to_table(&mut self, table: usize, text: &str) -> Result<()> {
+        let alphabet = self.alphabet();
+        let mut length = self.get_word(table);
+        for c in text.chars() {
+            let address = table + 2 + length as usize;
+            if address >= self.static_memory_base() as usize {
+                return Err(GameError::invalid_operation(
+                    "Output stream 3 table overflows dynamic memory",
+                ));
+            }
+            // Characters with no ZSCII equivalent are replaced with a question mark.
+            self.set_byte(address, alphabet.zscii_from_char(c).unwrap_or(b'?'));
+            length += 1;
+        }
+        self.set_word(table, length);
+        Ok(())
+    }
+
     /// Decode a Z-Character-encoded string, starting at the given point in memory.
     pub fn extract_string(&self, start: usize, abbreviations: bool) -> Result<(String, usize)> {
         let sequence = self.character_sequence(start);
@@ -774,8 +1056,20 @@ impl Memory {
         self.alphabet().zscii_from_code(code)
     }
 
-    pub fn parse_string(&mut self, mut cursor: usize, text: &str, max_words: usize) -> Result<()> {
-        let separators = self.word_separators()?;
+    /// Split text into words and write them to a parse buffer, with each word's dictionary
+    /// entry. The story's own dictionary is used unless another is given, and the words are
+    /// split using that dictionary's separators. If `skip_unrecognised` is set, the entries
+    /// for words missing from the dictionary are left as they were.
+    pub fn parse_string(
+        &mut self,
+        mut cursor: usize,
+        text: &str,
+        max_words: usize,
+        dictionary: Option<usize>,
+        skip_unrecognised: bool,
+    ) -> Result<()> {
+        let dictionary = dictionary.unwrap_or_else(|| self.dictionary_location());
+        let separators = self.word_separators(dictionary)?;
         let mut new_word = true;
         let words = text
             .chars()
@@ -800,15 +1094,19 @@ impl Memory {
 
         let words = words.iter().take(max_words);
 
-        let dictionary = self.dictionary()?;
         cursor += 1;
         self.write_byte(&mut cursor, words.len() as u8);
+        // The text starts after the length bytes at the start of the text buffer.
+        let text_start = if self.version() >= 5 { 2 } else { 1 };
 
         for (i, word) in words {
-            let dictionary_entry = dictionary.iter().find(|e| &e.1 == word);
-            let dictionary_address = dictionary_entry.map(|e| e.0 as u16).unwrap_or(0);
+            let dictionary_address = self.dictionary_lookup(dictionary, word) as u16;
+            if dictionary_address == 0 && skip_unrecognised {
+                cursor += 4;
+                continue;
+            }
             let chars = word.chars().count();
-            let buffer_offset = i + 1;
+            let buffer_offset = i + text_start;
 
             self.write_word(&mut cursor, dictionary_address);
             self.write_byte(&mut cursor, chars as u8);
@@ -864,11 +1162,6 @@ impl Memory {
             return Err(GameError::invalid_file());
         }
 
-        if self.version() == 6 {
-            error!("Version 6 file provided");
-            return Err(GameError::version_six());
-        }
-
         if self.version() > 8 || self.version() == 0 {
             // Version byte is outside expected/supported range
             error!("Invalid version byte");
@@ -895,7 +1188,11 @@ impl Memory {
             return Err(GameError::invalid_file());
         }
 
-        let program_counter_starts: usize = self.program_counter_starts().into();
+        // In version 6, the header holds the packed address of the main routine instead.
+        let program_counter_starts: usize = match self.version() {
+            6 => self.unpack_routine_address(self.program_counter_starts().into()),
+            _ => self.program_counter_starts().into(),
+        };
         if program_counter_starts < high_memory_base {
             error!("Program counter does not start in high memory");
             return Err(GameError::invalid_file());
@@ -908,3 +1205,183 @@ impl Memory {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A story of the given version with nothing in it but a header, and a header extension
+    /// table with room for the true default colours.
+    fn story(version: u8) -> Memory {
+        let mut data = vec![0; 0x100];
+        data[address::VERSION] = version;
+        data[address::HEADER_EXTENSION_TABLE_LOCATION + 1] = 0x40;
+        data[0x41] = address::TRUE_DEFAULT_BACKGROUND_COLOUR as u8;
+        data[address::STATIC_MEMORY_BASE] = 0x01;
+        Memory::new(data)
+    }
+
+    /// Write a dictionary at 0x80 with "." as its only separator and an entry for each word, in
+    /// the order given. The entry count is negative if the words aren't sorted. Returns the
+    /// address of each word's entry.
+    fn dictionary(memory: &mut Memory, words: &[&str], sorted: bool) -> Vec<usize> {
+        let key_length = if memory.version() <= 3 { 4 } else { 6 };
+        let count = words.len() as i16 * if sorted { 1 } else { -1 };
+        let mut data = vec![1, b'.', key_length as u8 + 1];
+        data.extend(count.to_be_bytes());
+        let mut entries = Vec::new();
+        for (i, word) in words.iter().enumerate() {
+            entries.push(0x80 + data.len());
+            data.extend(memory.encode_word(word));
+            data.push(i as u8);
+        }
+        memory.set_bytes(0x80, &data);
+        memory.set_word(address::DICTIONARY_LOCATION, 0x80);
+        entries
+    }
+
+    /// Pack Z-characters into a Z-string, three to a word, marking the last word.
+    fn zstring(zchars: &[u8]) -> Vec<u8> {
+        let words = zchars
+            .chunks(3)
+            .map(|chunk| ((chunk[0] as u16) << 10) | ((chunk[1] as u16) << 5) | (chunk[2] as u16));
+        let mut bytes: Vec<u8> = words.flat_map(u16::to_be_bytes).collect();
+        let last = bytes.len() - 2;
+        bytes[last] |= 0x80;
+        bytes
+    }
+
+    #[test]
+    fn words_are_padded_to_the_dictionary_length() {
+        assert_eq!(story(3).encode_word("ab"), zstring(&[6, 7, 5, 5, 5, 5]));
+        assert_eq!(
+            story(5).encode_word("ab"),
+            zstring(&[6, 7, 5, 5, 5, 5, 5, 5, 5])
+        );
+    }
+
+    #[test]
+    fn words_are_truncated_to_the_dictionary_length() {
+        assert_eq!(
+            story(3).encode_word("abcdefgh"),
+            zstring(&[6, 7, 8, 9, 10, 11])
+        );
+        assert_eq!(
+            story(5).encode_word("abcdefghijk"),
+            zstring(&[6, 7, 8, 9, 10, 11, 12, 13, 14])
+        );
+    }
+
+    #[test]
+    fn a2_characters_are_shifted() {
+        // '1' is the fourth character of A2, after the escape, newline and '0'.
+        assert_eq!(story(3).encode_word("a1"), zstring(&[6, 5, 9, 5, 5, 5]));
+        // Versions 1 and 2 use the single shift, character 3.
+        assert_eq!(story(2).encode_word("a1"), zstring(&[6, 3, 9, 5, 5, 5]));
+    }
+
+    #[test]
+    fn missing_characters_are_zscii_escapes() {
+        // '@' is ZSCII 64, which is split into its top and bottom five bits.
+        assert_eq!(story(3).encode_word("@"), zstring(&[5, 6, 2, 0, 5, 5]));
+        // An escape cut off at the end of the word is left incomplete.
+        assert_eq!(story(3).encode_word("abcd@"), zstring(&[6, 7, 8, 9, 5, 6]));
+    }
+
+    #[test]
+    fn encoded_text_is_written_to_dynamic_memory() {
+        let mut memory = story(5);
+        memory.set_bytes(0x80, b"xxab");
+        memory.encode_text(0x82, 2, 0x10).unwrap();
+        assert_eq!(
+            memory.get_bytes(0x10, 6),
+            zstring(&[6, 7, 5, 5, 5, 5, 5, 5, 5])
+        );
+    }
+
+    #[test]
+    fn encoding_out_of_bounds_is_an_error() {
+        let mut memory = story(5);
+        // The text runs past the end of memory.
+        assert!(memory.encode_text(0xFE, 4, 0x10).is_err());
+        // The result would run into static memory, which starts at 0x100.
+        assert!(memory.encode_text(0x80, 2, 0xFC).is_err());
+        assert!(memory.encode_text(0x80, 2, 0xFA).is_ok());
+    }
+
+    #[test]
+    fn default_colours_are_white_on_black() {
+        for version in [5, 6, 8] {
+            let mut memory = story(version);
+            memory.set_general_headers();
+            assert_eq!(memory.get_byte(address::DEFAULT_BACKGROUND_COLOUR), 2);
+            assert_eq!(memory.get_byte(address::DEFAULT_FOREGROUND_COLOUR), 9);
+            assert_eq!(
+                memory.get_word(0x40 + 2 * address::TRUE_DEFAULT_FOREGROUND_COLOUR),
+                0x7FFF
+            );
+            assert_eq!(
+                memory.get_word(0x40 + 2 * address::TRUE_DEFAULT_BACKGROUND_COLOUR),
+                0
+            );
+        }
+    }
+
+    #[test]
+    fn early_headers_have_no_colours() {
+        let mut memory = story(3);
+        memory.set_general_headers();
+        assert_eq!(memory.get_byte(address::DEFAULT_BACKGROUND_COLOUR), 0);
+        assert_eq!(memory.get_byte(address::DEFAULT_FOREGROUND_COLOUR), 0);
+    }
+
+    #[test]
+    fn sorted_dictionary_is_searched() {
+        let mut memory = story(3);
+        let words = ["apple", "bear", "cat", "door", "egg", "lantern"];
+        let entries = dictionary(&mut memory, &words, true);
+        for (word, &entry) in words.iter().zip(&entries) {
+            assert_eq!(memory.dictionary_lookup(0x80, word), entry);
+        }
+        // Only the first six characters of a word count in version 3.
+        assert_eq!(memory.dictionary_lookup(0x80, "lanterns"), entries[5]);
+        for missing in ["aardvark", "bird", "zebra"] {
+            assert_eq!(memory.dictionary_lookup(0x80, missing), 0);
+        }
+    }
+
+    #[test]
+    fn unsorted_dictionary_is_searched_in_order() {
+        let mut memory = story(5);
+        let words = ["egg", "apple", "door"];
+        let entries = dictionary(&mut memory, &words, false);
+        for (word, entry) in words.iter().zip(entries) {
+            assert_eq!(memory.dictionary_lookup(0x80, word), entry);
+        }
+        assert_eq!(memory.dictionary_lookup(0x80, "bear"), 0);
+    }
+
+    #[test]
+    fn unrecognised_words_can_be_skipped() {
+        let mut memory = story(5);
+        let entries = dictionary(&mut memory, &["door", "open"], true);
+        for skip_unrecognised in [false, true] {
+            memory.set_bytes(0xC0, &[10; 42]);
+            memory
+                .parse_string(0xC0, "open the door", 10, None, skip_unrecognised)
+                .unwrap();
+            // Every word is counted, even if its entry is skipped.
+            assert_eq!(memory.get_byte(0xC1), 3);
+            assert_eq!(memory.get_word(0xC2), entries[1] as u16);
+            assert_eq!(memory.get_bytes(0xC4, 2), [4, 2]);
+            let unrecognised = if skip_unrecognised {
+                [10; 4]
+            } else {
+                [0, 0, 3, 7]
+            };
+            assert_eq!(memory.get_bytes(0xC6, 4), unrecognised);
+            assert_eq!(memory.get_word(0xCA), entries[0] as u16);
+            assert_eq!(memory.get_bytes(0xCC, 2), [4, 11]);
+        }
+    }
+}
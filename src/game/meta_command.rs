@@ -0,0 +1,188 @@
+//! Interpreter commands, which the player types at the game's prompt but which are handled by
+//! the interpreter and never reach the game. Each starts with an escape prefix ("/" unless the
+//! player chooses another, for games that use it). Typing the prefix twice sends the rest of
+//! the line to the game with a single prefix.
+
+/// An interpreter command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetaCommand {
+    /// Take back the last command.
+    Undo,
+    Restore,
+    /// Start or stop the transcript (output stream 2).
+    Transcript(bool),
+    /// Seed the random number generator, so that what happens next can be repeated.
+    Seed(u64),
+    Quit,
+    Help,
+    /// Show the tree of objects.
+    ObjectTree,
+}
+
+/// What a line of input turned out to be.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// A line for the game.
+    Game(String),
+    Command(MetaCommand),
+    /// A line that started with the prefix but wasn't a command. Holds a message explaining
+    /// what was wrong.
+    Invalid(String),
+}
+
+/// The usage and description of each command, without the prefix.
+const COMMANDS: &[(&str, &str)] = &[
+    ("undo", "Take back the last command"),
+    ("restore", "Restore a saved game"),
+    ("transcript on|off", "Start or stop the transcript"),
+    ("seed NUMBER", "Seed the random number generator"),
+    ("quit", "Quit the game"),
+    ("objtree", "Show the object tree"),
+    ("help", "List these commands"),
+];
+
+/// Work out whether a line of input is an interpreter command. With an empty prefix, nothing
+/// is.
+pub fn parse(line: &str, prefix: &str) -> Input {
+    let command = match line.trim_start().strip_prefix(prefix) {
+        Some(command) if !prefix.is_empty() => command,
+        _ => return Input::Game(line.to_string()),
+    };
+    if command.starts_with(prefix) {
+        return Input::Game(command.to_string());
+    }
+    let mut words = command.split_whitespace();
+    let name = words.next().unwrap_or("").to_lowercase();
+    let argument = words.next().map(str::to_lowercase);
+    let command = match (name.as_str(), argument.as_deref(), words.next()) {
+        ("undo", None, None) => MetaCommand::Undo,
+        ("restore", None, None) => MetaCommand::Restore,
+        ("transcript", Some("on"), None) => MetaCommand::Transcript(true),
+        ("transcript", Some("off"), None) => MetaCommand::Transcript(false),
+        ("seed", Some(seed), None) => match seed.parse() {
+            Ok(seed) => MetaCommand::Seed(seed),
+            Err(_) => return Input::Invalid(format!("\"{}\" is not a seed.", seed)),
+        },
+        ("quit", None, None) => MetaCommand::Quit,
+        ("help", None, None) => MetaCommand::Help,
+        ("objtree", None, None) => MetaCommand::ObjectTree,
+        _ => {
+            let usage = COMMANDS
+                .iter()
+                .find(|(usage, _)| usage.split(' ').next() == Some(name.as_str()));
+            return Input::Invalid(match usage {
+                Some((usage, _)) => format!("Usage: {}{}", prefix, usage),
+                None => format!("Unknown command. Type {}help for a list.", prefix),
+            });
+        }
+    };
+    Input::Command(command)
+}
+
+/// The text of the help command.
+pub fn help(prefix: &str) -> String {
+    let width = COMMANDS
+        .iter()
+        .map(|(usage, _)| usage.len())
+        .max()
+        .unwrap_or(0);
+    let mut help = String::from("Interpreter commands:\n");
+    for (usage, description) in COMMANDS {
+        help.push_str(&format!(
+            "  {}{:width$}  {}\n",
+            prefix,
+            usage,
+            description,
+            width = width
+        ));
+    }
+    help.push_str(&format!(
+        "Type {}{} to send a line starting with {} to the game.\n",
+        prefix, prefix, prefix
+    ));
+    help
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(command: MetaCommand) -> Input {
+        Input::Command(command)
+    }
+
+    fn game(line: &str) -> Input {
+        Input::Game(line.to_string())
+    }
+
+    fn invalid(message: &str) -> Input {
+        Input::Invalid(message.to_string())
+    }
+
+    #[test]
+    fn lines_are_parsed() {
+        for (line, expected) in [
+            ("look", game("look")),
+            ("", game("")),
+            ("/undo", command(MetaCommand::Undo)),
+            ("  /UNDO  ", command(MetaCommand::Undo)),
+            ("/restore", command(MetaCommand::Restore)),
+            ("/transcript on", command(MetaCommand::Transcript(true))),
+            ("/transcript OFF", command(MetaCommand::Transcript(false))),
+            ("/seed 1234", command(MetaCommand::Seed(1234))),
+            ("/quit", command(MetaCommand::Quit)),
+            ("/help", command(MetaCommand::Help)),
+            ("/objtree", command(MetaCommand::ObjectTree)),
+            // A doubled prefix sends the line to the game with a single one.
+            ("//undo", game("/undo")),
+            ("//", game("/")),
+        ] {
+            assert_eq!(parse(line, "/"), expected, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn mistakes_are_explained() {
+        for (line, expected) in [
+            ("/", invalid("Unknown command. Type /help for a list.")),
+            ("/save", invalid("Unknown command. Type /help for a list.")),
+            (
+                "/frobnicate",
+                invalid("Unknown command. Type /help for a list."),
+            ),
+            ("/undo twice", invalid("Usage: /undo")),
+            ("/transcript", invalid("Usage: /transcript on|off")),
+            ("/transcript maybe", invalid("Usage: /transcript on|off")),
+            ("/seed", invalid("Usage: /seed NUMBER")),
+            ("/seed -1", invalid("\"-1\" is not a seed.")),
+            ("/seed 1 2", invalid("Usage: /seed NUMBER")),
+        ] {
+            assert_eq!(parse(line, "/"), expected, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn other_prefixes_are_used() {
+        for (line, expected) in [
+            ("#undo", command(MetaCommand::Undo)),
+            ("##undo", game("#undo")),
+            ("/undo", game("/undo")),
+            (
+                "#frobnicate",
+                invalid("Unknown command. Type #help for a list."),
+            ),
+        ] {
+            assert_eq!(parse(line, "#"), expected, "{:?}", line);
+        }
+        // With no prefix, every line goes to the game.
+        assert_eq!(parse("undo", ""), game("undo"));
+    }
+
+    #[test]
+    fn help_lists_commands_with_the_prefix() {
+        let help = help("#");
+        assert!(help.contains("  #undo "));
+        assert!(help.contains("  #transcript on|off  Start or stop the transcript\n"));
+        assert!(help.ends_with("Type ## to send a line starting with # to the game.\n"));
+    }
+}
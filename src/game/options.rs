@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+use crate::game::undo;
+
+/// Interpreter settings that don't come from the story file.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The name of the story, used to suggest names for save files.
+    pub story_name: String,
+    /// A command file to read input from, before reading from the keyboard.
+    pub replay: Option<PathBuf>,
+    /// A command file to record the player's input to, using output stream 4.
+    pub record: Option<PathBuf>,
+    /// A directory to write each frame of the version 6 screen to, as PNG files.
+    pub frames: Option<PathBuf>,
+    /// The directory for the story's auxiliary files. By default, this is a directory named
+    /// after the story in the user's data directory.
+    pub auxiliary_directory: Option<PathBuf>,
+    /// The number of undo states to keep. 0 turns undo off.
+    pub undo_depth: usize,
+    /// The prefix that marks interpreter commands, such as `/undo`, typed at the game's prompt.
+    /// An empty prefix turns them off.
+    pub command_prefix: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            story_name: "story".to_string(),
+            replay: None,
+            record: None,
+            frames: None,
+            auxiliary_directory: None,
+            undo_depth: undo::DEFAULT_DEPTH,
+            command_prefix: "/".to_string(),
+        }
+    }
+}
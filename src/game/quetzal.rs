@@ -0,0 +1,375 @@
+//! Save files in the Quetzal format, as used by most Z-Machine interpreters.
+//! Refer to the Quetzal specification (version 1.4) for details.
+
+use std::io::Cursor;
+
+use crate::game::error::GameError;
+use crate::game::stack::{CallStack, StackFrame};
+use crate::game::Result;
+use crate::loader::iff::{Chunk, DataChunk, FormChunk, IffReader};
+
+/// A snapshot of everything needed to resume a game.
+pub struct SaveState {
+    pub release_number: u16,
+    pub serial_number: [u8; 6],
+    pub checksum: u16,
+    /// The address of the store byte (v4+) or branch data (v1-3) of the save instruction.
+    pub pc: usize,
+    pub dynamic_memory: Vec<u8>,
+    pub call_stack: CallStack,
+}
+
+impl SaveState {
+    /// Serialize the save state to a Quetzal file. The original contents of dynamic memory
+    /// are needed to compress the memory image, and the story's version to encode its stack.
+    pub fn to_bytes(&self, original_memory: &[u8], version: u8) -> Vec<u8> {
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&self.release_number.to_be_bytes());
+        header.extend_from_slice(&self.serial_number);
+        header.extend_from_slice(&self.checksum.to_be_bytes());
+        header.extend_from_slice(&(self.pc as u32).to_be_bytes()[1..]);
+
+        Chunk::Form(FormChunk::new(
+            *b"IFZS",
+            vec![
+                Chunk::Data(DataChunk::new(*b"IFhd", header)),
+                Chunk::Data(DataChunk::new(
+                    *b"CMem",
+                    compress_memory(&self.dynamic_memory, original_memory),
+                )),
+                Chunk::Data(DataChunk::new(
+                    *b"Stks",
+                    encode_stacks(&self.call_stack, version),
+                )),
+            ],
+        ))
+        .to_bytes()
+    }
+
+    /// Read a Quetzal file for a story of the given version.
+    pub fn from_bytes(data: &[u8], original_memory: &[u8], version: u8) -> Result<SaveState> {
+        let form = match IffReader::new(Cursor::new(data))
+            .load()
+            .map_err(|e| GameError::invalid_save_file(e.to_string()))?
+        {
+            Chunk::Form(form) if form.kind() == b"IFZS" => form,
+            _ => return Err(GameError::invalid_save_file("Not a Quetzal file")),
+        };
+
+        let header = form
+            .data_chunk(b"IFhd")
+            .map(|chunk| chunk.data())
+            .filter(|data| data.len() >= 13)
+            .ok_or_else(|| GameError::invalid_save_file("Missing or invalid IFhd chunk"))?;
+
+        let dynamic_memory = if let Some(chunk) = form.data_chunk(b"CMem") {
+            decompress_memory(chunk.data(), original_memory)?
+        } else if let Some(chunk) = form.data_chunk(b"UMem") {
+            if chunk.data().len() != original_memory.len() {
+                return Err(GameError::invalid_save_file(
+                    "UMem chunk is the wrong length",
+                ));
+            }
+            chunk.data().to_vec()
+        } else {
+            return Err(GameError::invalid_save_file("Missing memory chunk"));
+        };
+
+        let pc = u32::from_be_bytes([0, header[10], header[11], header[12]]) as usize;
+
+        let call_stack = decode_stacks(
+            form.data_chunk(b"Stks")
+                .ok_or_else(|| GameError::invalid_save_file("Missing Stks chunk"))?
+                .data(),
+            pc,
+            version,
+        )?;
+
+        Ok(SaveState {
+            release_number: u16::from_be_bytes([header[0], header[1]]),
+            serial_number: header[2..8].try_into().unwrap(),
+            checksum: u16::from_be_bytes([header[8], header[9]]),
+            pc,
+            dynamic_memory,
+            call_stack,
+        })
+    }
+}
+
+/// XOR the memory with its original state, then run-length encode the zeros. Trailing zeros
+/// are omitted. This is also how undo snapshots are kept.
+pub fn compress_memory(memory: &[u8], original: &[u8]) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut zeros = 0usize;
+    for (current, original) in memory.iter().zip(original.iter()) {
+        let byte = current ^ original;
+        if byte == 0 {
+            zeros += 1;
+            continue;
+        }
+        while zeros > 0 {
+            let run = zeros.min(256);
+            result.push(0);
+            result.push((run - 1) as u8);
+            zeros -= run;
+        }
+        result.push(byte);
+    }
+    result
+}
+
+pub fn decompress_memory(data: &[u8], original: &[u8]) -> Result<Vec<u8>> {
+    let mut result = original.to_vec();
+    let mut position = 0;
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == 0 {
+            let run = *bytes
+                .next()
+                .ok_or_else(|| GameError::invalid_save_file("CMem chunk ended unexpectedly"))?;
+            position += run as usize + 1;
+        } else {
+            *result
+                .get_mut(position)
+                .ok_or_else(|| GameError::invalid_save_file("CMem chunk is too long"))? ^= byte;
+            position += 1;
+        }
+    }
+    if position > original.len() {
+        return Err(GameError::invalid_save_file("CMem chunk is too long"));
+    }
+    Ok(result)
+}
+
+/// Encode the call stack. Each Quetzal frame stores the PC of its caller, so the PC of the
+/// topmost frame is not included (it is stored in the IFhd chunk instead). Before version 6,
+/// the code outside any routine is represented by a dummy frame with no locals; in version 6,
+/// the main routine has a real frame, with no caller and its result discarded.
+fn encode_stacks(call_stack: &CallStack, version: u8) -> Vec<u8> {
+    let frames = call_stack.frames();
+    let mut result = Vec::new();
+    for (i, frame) in frames.iter().enumerate() {
+        if i == 0 && version != 6 {
+            result.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        } else {
+            let return_pc = match i {
+                0 => 0,
+                _ => frames[i - 1].pc,
+            };
+            result.extend_from_slice(&(return_pc as u32).to_be_bytes()[1..]);
+            let mut flags = frame.locals.len() as u8;
+            if frame.store_to.is_none() {
+                flags |= 0x10;
+            }
+            result.push(flags);
+            result.push(frame.store_to.unwrap_or(0));
+            result.push(((1u16 << frame.arg_count) - 1) as u8);
+        }
+        result.extend_from_slice(&(frame.stack.len() as u16).to_be_bytes());
+        for value in frame.locals.iter().chain(frame.stack.iter()) {
+            result.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+    result
+}
+
+fn decode_stacks(data: &[u8], pc: usize, version: u8) -> Result<CallStack> {
+    let mut cursor = 0;
+    let mut return_pcs = Vec::new();
+    let mut frames = Vec::new();
+    while cursor < data.len() {
+        let header = read_bytes(data, &mut cursor, 8)?;
+        let return_pc = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        let flags = header[3];
+        let variable = header[4];
+        let arguments = header[5];
+        let stack_size = u16::from_be_bytes([header[6], header[7]]) as usize;
+
+        if frames.is_empty() && version != 6 && (flags & 0xf != 0 || arguments != 0) {
+            return Err(GameError::invalid_save_file(
+                "The dummy stack frame has locals or arguments",
+            ));
+        }
+
+        let locals = read_words(data, &mut cursor, (flags & 0xf) as usize)?;
+        let stack = read_words(data, &mut cursor, stack_size)?;
+
+        // Nothing can receive the result of the main routine.
+        let store_to = if frames.is_empty() || flags & 0x10 != 0 {
+            None
+        } else {
+            Some(variable)
+        };
+        let arg_count = (!arguments).trailing_zeros() as usize;
+        let mut frame = StackFrame::new(0, locals, arg_count, store_to);
+        frame.stack = stack;
+        frames.push(frame);
+        return_pcs.push(return_pc);
+    }
+
+    if frames.is_empty() {
+        return Err(GameError::invalid_save_file(
+            "Stks chunk contains no frames",
+        ));
+    }
+
+    // Each frame resumes at the return PC stored by the frame above it.
+    for (frame, pc) in frames
+        .iter_mut()
+        .zip(return_pcs.into_iter().skip(1).chain([pc]))
+    {
+        frame.pc = pc;
+    }
+
+    Ok(CallStack::from_frames(frames))
+}
+
+fn read_bytes<'a>(data: &'a [u8], cursor: &mut usize, count: usize) -> Result<&'a [u8]> {
+    let bytes = data
+        .get(*cursor..*cursor + count)
+        .ok_or_else(|| GameError::invalid_save_file("Stks chunk ended unexpectedly"))?;
+    *cursor += count;
+    Ok(bytes)
+}
+
+fn read_words(data: &[u8], cursor: &mut usize, count: usize) -> Result<Vec<u16>> {
+    Ok(read_bytes(data, cursor, count * 2)?
+        .chunks(2)
+        .map(|word| u16::from_be_bytes([word[0], word[1]]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(pc: usize, locals: Vec<u16>, store_to: Option<u8>, stack: Vec<u16>) -> StackFrame {
+        let mut frame = StackFrame::new(pc, locals, 1, store_to);
+        frame.stack = stack;
+        frame
+    }
+
+    fn save_state(dynamic_memory: Vec<u8>) -> SaveState {
+        SaveState {
+            release_number: 88,
+            serial_number: *b"840726",
+            checksum: 0xA129,
+            pc: 0x4F05,
+            dynamic_memory,
+            call_stack: CallStack::from_frames(vec![
+                // The code outside any routine has no locals and no arguments.
+                StackFrame {
+                    stack: vec![5, 6],
+                    ..StackFrame::new(0x4000, vec![], 0, None)
+                },
+                frame(0x4F05, vec![1, 0xFFFF], Some(0x10), vec![]),
+            ]),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let original = vec![0; 600];
+        let mut memory = original.clone();
+        memory[3] = 1;
+        memory[599] = 0xAB;
+        let state = save_state(memory.clone());
+
+        let data = state.to_bytes(&original, 5);
+        let loaded = SaveState::from_bytes(&data, &original, 5).unwrap();
+        assert_eq!(loaded.release_number, 88);
+        assert_eq!(&loaded.serial_number, b"840726");
+        assert_eq!(loaded.checksum, 0xA129);
+        assert_eq!(loaded.pc, 0x4F05);
+        assert_eq!(loaded.dynamic_memory, memory);
+        assert_eq!(loaded.call_stack, state.call_stack);
+    }
+
+    #[test]
+    fn uncompressed_memory() {
+        let original = vec![0; 4];
+        let state = save_state(vec![]);
+        let data = Chunk::Form(FormChunk::new(
+            *b"IFZS",
+            vec![
+                Chunk::Data(DataChunk::new(
+                    *b"IFhd",
+                    vec![0, 88, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10, 0],
+                )),
+                Chunk::Data(DataChunk::new(*b"UMem", vec![1, 2, 3, 4])),
+                Chunk::Data(DataChunk::new(
+                    *b"Stks",
+                    encode_stacks(&state.call_stack, 5),
+                )),
+            ],
+        ))
+        .to_bytes();
+
+        let loaded = SaveState::from_bytes(&data, &original, 5).unwrap();
+        assert_eq!(loaded.release_number, 88);
+        assert_eq!(loaded.pc, 0x1000);
+        assert_eq!(loaded.dynamic_memory, [1, 2, 3, 4]);
+        // UMem must be exactly the size of dynamic memory.
+        assert!(SaveState::from_bytes(&data, &[0; 5], 5).is_err());
+    }
+
+    #[test]
+    fn header_chunk_is_padded() {
+        let original = vec![0; 8];
+        let data = save_state(original.clone()).to_bytes(&original, 5);
+        // IFhd holds 13 bytes, so a padding byte comes before the next chunk.
+        assert_eq!(&data[12..20], b"IFhd\0\0\0\x0D");
+        assert_eq!(data[33], 0);
+        assert_eq!(&data[34..38], b"CMem");
+    }
+
+    #[test]
+    fn long_runs_of_zeros() {
+        let original = vec![0x55; 1000];
+        let mut memory = original.clone();
+        memory[600] = 0x54;
+        let compressed = compress_memory(&memory, &original);
+        // 600 unchanged bytes take three runs, and the unchanged bytes at the end are left
+        // out.
+        assert_eq!(compressed, [0, 255, 0, 255, 0, 87, 1]);
+        assert_eq!(decompress_memory(&compressed, &original).unwrap(), memory);
+    }
+
+    #[test]
+    fn unchanged_memory_compresses_to_nothing() {
+        let original = vec![7; 300];
+        assert!(compress_memory(&original, &original).is_empty());
+        assert_eq!(decompress_memory(&[], &original).unwrap(), original);
+    }
+
+    #[test]
+    fn run_past_the_end_is_rejected() {
+        let original = vec![0; 10];
+        assert!(decompress_memory(&[0, 9, 1], &original).is_err());
+        assert!(decompress_memory(&[0, 10], &original).is_err());
+        // A run with no length.
+        assert!(decompress_memory(&[1, 0], &original).is_err());
+    }
+
+    #[test]
+    fn version_6_main_routine_has_a_real_frame() {
+        let call_stack = CallStack::from_frames(vec![
+            frame(0x1234, vec![1, 2, 3], None, vec![7]),
+            frame(0x2000, vec![9], Some(0x10), vec![]),
+        ]);
+        let data = encode_stacks(&call_stack, 6);
+        // No return PC, three locals with the result discarded, one argument, one word on the
+        // stack.
+        assert_eq!(data[..8], [0, 0, 0, 0x13, 0, 0x01, 0, 1]);
+
+        let decoded = decode_stacks(&data, 0x2000, 6).unwrap();
+        assert_eq!(decoded, call_stack);
+    }
+
+    #[test]
+    fn dummy_frame_with_locals_is_rejected() {
+        let call_stack = CallStack::from_frames(vec![frame(0x1234, vec![1], None, vec![])]);
+        let data = encode_stacks(&call_stack, 6);
+        assert!(decode_stacks(&data, 0x1234, 5).is_err());
+    }
+}
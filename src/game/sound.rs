@@ -0,0 +1,513 @@
+//! Sound effects, played from the `Snd ` resources of a Blorb file.
+//!
+//! The game state keeps track of which sound is playing and which routine to call when it ends;
+//! a `SoundBackend` does the playing.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+use tracing::{info, warn};
+
+use crate::game::error::GameError;
+use crate::game::Result;
+use crate::loader::blorb::{ChunkKind, SoundFormat};
+use crate::loader::BlorbLoader;
+
+/// The number of times to play a sound that plays until it's stopped.
+pub const FOREVER: u8 = 255;
+
+/// How often to check whether a sound has finished.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Plays sounds.
+pub trait SoundBackend {
+    /// Start playing a sound, stopping any sound already playing. The volume runs from 1 to 8,
+    /// and the sound is played the given number of times (or until it's stopped, for FOREVER).
+    fn play(
+        &mut self,
+        number: u16,
+        format: &SoundFormat,
+        data: &[u8],
+        volume: u8,
+        repeats: u8,
+    ) -> Result<()>;
+
+    /// Stop the sound that's playing, if there is one.
+    fn stop(&mut self) -> Result<()>;
+
+    /// Whether the last sound started is still playing.
+    fn playing(&mut self) -> bool;
+
+    /// Play one of the two built-in bleeps.
+    fn bleep(&mut self, high: bool) -> Result<()>;
+}
+
+/// Something that happened to a `NullBackend`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SoundEvent {
+    Play {
+        number: u16,
+        volume: u8,
+        repeats: u8,
+    },
+    Stop,
+    Bleep {
+        high: bool,
+    },
+}
+
+/// A backend that plays nothing, but logs what it's asked to do. Every sound ends as soon as it
+/// starts.
+#[derive(Debug, Default)]
+pub struct NullBackend {
+    pub events: Vec<SoundEvent>,
+}
+
+impl NullBackend {
+    fn record(&mut self, event: SoundEvent) {
+        info!("Sound: {:?}", event);
+        self.events.push(event);
+    }
+}
+
+impl SoundBackend for NullBackend {
+    fn play(
+        &mut self,
+        number: u16,
+        _: &SoundFormat,
+        _: &[u8],
+        volume: u8,
+        repeats: u8,
+    ) -> Result<()> {
+        self.record(SoundEvent::Play {
+            number,
+            volume,
+            repeats,
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.record(SoundEvent::Stop);
+        Ok(())
+    }
+
+    fn playing(&mut self) -> bool {
+        false
+    }
+
+    fn bleep(&mut self, high: bool) -> Result<()> {
+        self.record(SoundEvent::Bleep { high });
+        Ok(())
+    }
+}
+
+/// A backend that plays each sound with an external player program. The sound is written to a
+/// file in a private temporary directory, and the player's arguments may include `{file}` (the
+/// file to play) and `{volume}` (from 0 to 100).
+pub struct PlayerBackend {
+    command: Vec<String>,
+    /// Removed, with anything left in it, when the backend is dropped.
+    directory: TempDir,
+    child: Option<Child>,
+    file: Option<PathBuf>,
+    volume: u8,
+    /// The number of times left to start the player, after the current one. Not used for sounds
+    /// that repeat forever.
+    repeats_left: u8,
+    forever: bool,
+}
+
+impl PlayerBackend {
+    /// Create a backend from a command line, such as `ffplay -nodisp -autoexit {file}`. Fails
+    /// if the player can't be found.
+    pub fn new(command: &str) -> Result<PlayerBackend> {
+        let command: Vec<String> = command.split_whitespace().map(str::to_string).collect();
+        match command.first() {
+            None => {
+                return Err(GameError::invalid_operation(
+                    "The sound player command is empty",
+                ))
+            }
+            Some(program) if !program_exists(program) => {
+                return Err(GameError::invalid_operation(format!(
+                    "The sound player {} was not found",
+                    program
+                )))
+            }
+            _ => {}
+        }
+        Ok(PlayerBackend {
+            command,
+            directory: tempfile::Builder::new().prefix("zanthe-").tempdir()?,
+            child: None,
+            file: None,
+            volume: 8,
+            repeats_left: 0,
+            forever: false,
+        })
+    }
+
+    fn start_player(&mut self) -> Result<()> {
+        let file = match &self.file {
+            Some(file) => file.to_string_lossy().into_owned(),
+            None => return Ok(()),
+        };
+        let volume = (self.volume as u16 * 100 / 8).to_string();
+        let arguments = self.command[1..].iter().map(|argument| {
+            argument
+                .replace("{file}", &file)
+                .replace("{volume}", &volume)
+        });
+        self.child = Some(
+            Command::new(&self.command[0])
+                .args(arguments)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?,
+        );
+        Ok(())
+    }
+}
+
+impl SoundBackend for PlayerBackend {
+    fn play(
+        &mut self,
+        number: u16,
+        format: &SoundFormat,
+        data: &[u8],
+        volume: u8,
+        repeats: u8,
+    ) -> Result<()> {
+        self.stop()?;
+        let extension = match format {
+            SoundFormat::Aiff => "aiff",
+            SoundFormat::Ogg => "ogg",
+            SoundFormat::Mod => "mod",
+            _ => {
+                warn!("Sound {} is in an unsupported format", number);
+                return Ok(());
+            }
+        };
+        let file = self
+            .directory
+            .path()
+            .join(format!("sound{}.{}", number, extension));
+        // The directory is ours alone, but never write through anything already there.
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&file)?
+            .write_all(data)?;
+        self.file = Some(file);
+        self.volume = volume;
+        self.forever = repeats == FOREVER;
+        self.repeats_left = repeats.saturating_sub(1);
+        self.start_player()
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        if let Some(mut child) = self.child.take() {
+            // The player may have finished already.
+            let _ = child.kill();
+            child.wait()?;
+        }
+        if let Some(file) = self.file.take() {
+            fs::remove_file(file)?;
+        }
+        Ok(())
+    }
+
+    fn playing(&mut self) -> bool {
+        let finished = match &mut self.child {
+            Some(child) => !matches!(child.try_wait(), Ok(None)),
+            None => return false,
+        };
+        if !finished {
+            return true;
+        }
+        self.child = None;
+        if self.forever || self.repeats_left > 0 {
+            self.repeats_left = self.repeats_left.saturating_sub(1);
+            if let Err(e) = self.start_player() {
+                warn!("Could not repeat sound: {}", e);
+                return false;
+            }
+            return true;
+        }
+        false
+    }
+
+    fn bleep(&mut self, _high: bool) -> Result<()> {
+        // The terminal bell is the only bleep available.
+        let mut stderr = io::stderr();
+        stderr.write_all(b"\x07")?;
+        stderr.flush()?;
+        Ok(())
+    }
+}
+
+/// Whether a program can be run: either a path to a file, or the name of a file in one of the
+/// directories on the `PATH`.
+fn program_exists(program: &str) -> bool {
+    let program = Path::new(program);
+    if program.components().count() > 1 {
+        return program.is_file();
+    }
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+impl Drop for PlayerBackend {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// The sound effects of a game: the resources to play, the backend to play them with, and the
+/// sound playing now.
+pub struct Sounds {
+    backend: Box<dyn SoundBackend>,
+    resources: Option<Rc<BlorbLoader>>,
+    /// The sound playing, and the routine (if any) to call when it finishes.
+    current: Option<(u16, Option<usize>)>,
+    last_poll: Instant,
+}
+
+impl Sounds {
+    pub fn new(backend: Box<dyn SoundBackend>, resources: Option<Rc<BlorbLoader>>) -> Sounds {
+        Sounds {
+            backend,
+            resources,
+            current: None,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Whether there are any sounds to play.
+    pub fn available(&self) -> bool {
+        self.resources.iter().any(|resources| {
+            resources
+                .chunks()
+                .any(|chunk| matches!(chunk.kind, ChunkKind::Sound { .. }))
+        })
+    }
+
+    pub fn bleep(&mut self, high: bool) -> Result<()> {
+        self.backend.bleep(high)
+    }
+
+    /// Start playing a sound. A repeat count of 0 plays the sound as many times as the
+    /// resource file says (once, unless its `Loop` chunk says otherwise). The routine, if
+    /// given, is called when the sound finishes on its own (but not if it's stopped).
+    pub fn start(
+        &mut self,
+        number: u16,
+        volume: u8,
+        repeats: u8,
+        routine: Option<usize>,
+    ) -> Result<()> {
+        let resources = match &self.resources {
+            Some(resources) => resources,
+            None => return Ok(()),
+        };
+        let chunk = match resources.sound(number.into()) {
+            Some(chunk) => chunk,
+            None => {
+                warn!("Sound {} not found", number);
+                return Ok(());
+            }
+        };
+        let format = match &chunk.kind {
+            ChunkKind::Sound { format } => format,
+            _ => return Ok(()),
+        };
+        let repeats = match repeats {
+            0 => match resources.sound_loop(number.into()) {
+                Some(0) => FOREVER,
+                Some(count) => count.min(FOREVER as u32 - 1) as u8,
+                None => 1,
+            },
+            repeats => repeats,
+        };
+        // A sound that can't be played isn't worth stopping the game for.
+        if let Err(e) = self
+            .backend
+            .play(number, format, chunk.data, volume, repeats)
+        {
+            warn!("Could not play sound {}: {}", number, e);
+            return Ok(());
+        }
+        self.current = Some((number, routine));
+        self.last_poll = Instant::now();
+        Ok(())
+    }
+
+    /// Stop a sound, if it's the one playing. Its routine isn't called.
+    pub fn stop(&mut self, number: u16) -> Result<()> {
+        match self.current {
+            Some((current, _)) if current == number || number == 0 => {
+                self.current = None;
+                self.backend.stop()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Unload a sound, which the game is finished with. Sounds are never kept loaded, so this
+    /// only stops it, without calling its routine.
+    pub fn finish_with(&mut self, number: u16) -> Result<()> {
+        self.stop(number)
+    }
+
+    /// Stop whatever sound is playing.
+    pub fn stop_all(&mut self) -> Result<()> {
+        self.stop(0)
+    }
+
+    /// Whether a routine is waiting for the sound playing to finish.
+    pub fn waiting(&self) -> bool {
+        matches!(self.current, Some((_, Some(_))))
+    }
+
+    /// Check whether the sound playing has finished. If it has, returns the routine to call.
+    /// Sounds are only checked every so often, so this is cheap to call between instructions.
+    pub fn poll(&mut self) -> Option<usize> {
+        if self.current.is_none() || self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+        if self.backend.playing() {
+            return None;
+        }
+        self.current.take().and_then(|(_, routine)| routine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::thread;
+
+    use super::*;
+    use crate::loader::iff::{Chunk, DataChunk, FormChunk};
+
+    /// Lets a test look at what the backend was asked to do after handing it over.
+    impl SoundBackend for Rc<RefCell<NullBackend>> {
+        fn play(
+            &mut self,
+            number: u16,
+            format: &SoundFormat,
+            data: &[u8],
+            volume: u8,
+            repeats: u8,
+        ) -> Result<()> {
+            self.borrow_mut()
+                .play(number, format, data, volume, repeats)
+        }
+
+        fn stop(&mut self) -> Result<()> {
+            self.borrow_mut().stop()
+        }
+
+        fn playing(&mut self) -> bool {
+            self.borrow_mut().playing()
+        }
+
+        fn bleep(&mut self, high: bool) -> Result<()> {
+            self.borrow_mut().bleep(high)
+        }
+    }
+
+    /// A Blorb file holding sound 3.
+    fn resources() -> Rc<BlorbLoader> {
+        // The sound's chunk follows the 12-byte form header and the 24-byte index.
+        let index = [
+            &1u32.to_be_bytes()[..],
+            b"Snd ",
+            &3u32.to_be_bytes(),
+            &36u32.to_be_bytes(),
+        ]
+        .concat();
+        let file = Chunk::Form(FormChunk::new(
+            *b"IFRS",
+            vec![
+                Chunk::Data(DataChunk::new(*b"RIdx", index)),
+                Chunk::Data(DataChunk::new(*b"OGGV", b"sound".to_vec())),
+            ],
+        ))
+        .to_bytes();
+        Rc::new(BlorbLoader::new(file).unwrap())
+    }
+
+    fn sounds() -> (Sounds, Rc<RefCell<NullBackend>>) {
+        let backend = Rc::new(RefCell::new(NullBackend::default()));
+        let sounds = Sounds::new(Box::new(backend.clone()), Some(resources()));
+        (sounds, backend)
+    }
+
+    /// Poll until well after the sound could have ended, collecting the routines returned.
+    fn poll_until_quiet(sounds: &mut Sounds) -> Vec<usize> {
+        let mut routines = Vec::new();
+        for _ in 0..3 {
+            thread::sleep(POLL_INTERVAL);
+            routines.extend(sounds.poll());
+        }
+        routines
+    }
+
+    #[test]
+    fn routine_is_called_once_when_the_sound_ends() {
+        let (mut sounds, backend) = sounds();
+        assert!(sounds.available());
+        sounds.start(3, 8, 2, Some(0x1234)).unwrap();
+        assert_eq!(
+            backend.borrow().events,
+            [SoundEvent::Play {
+                number: 3,
+                volume: 8,
+                repeats: 2
+            }]
+        );
+        assert!(sounds.waiting());
+        // Sounds aren't checked again straight away.
+        assert_eq!(sounds.poll(), None);
+        assert_eq!(poll_until_quiet(&mut sounds), [0x1234]);
+        assert!(!sounds.waiting());
+    }
+
+    #[test]
+    fn stopping_cancels_the_routine() {
+        let (mut sounds, backend) = sounds();
+        sounds.start(3, 8, 2, Some(0x1234)).unwrap();
+        // Stopping another sound leaves this one alone.
+        sounds.stop(4).unwrap();
+        assert!(sounds.waiting());
+        sounds.stop(3).unwrap();
+        assert_eq!(backend.borrow().events.last(), Some(&SoundEvent::Stop));
+        assert!(poll_until_quiet(&mut sounds).is_empty());
+    }
+
+    #[test]
+    fn finishing_with_cancels_the_routine() {
+        let (mut sounds, _) = sounds();
+        sounds.start(3, 8, 1, Some(0x1234)).unwrap();
+        sounds.finish_with(3).unwrap();
+        assert!(poll_until_quiet(&mut sounds).is_empty());
+    }
+
+    #[test]
+    fn missing_sound_has_no_routine() {
+        let (mut sounds, backend) = sounds();
+        sounds.start(5, 8, 1, Some(0x1234)).unwrap();
+        assert!(backend.borrow().events.is_empty());
+        assert!(poll_until_quiet(&mut sounds).is_empty());
+    }
+}
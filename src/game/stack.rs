@@ -4,13 +4,13 @@ use crate::game::error::GameError;
 use crate::game::instruction::Result as InstructionResult;
 
 /// The call-stack of the machine. Divided into stack frames, representing individual routines.
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CallStack {
     frames: Vec<StackFrame>,
 }
 
 /// The section of the call stack associated with a particular routine.
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StackFrame {
     pub pc: usize,
     pub stack: Vec<u16>,
@@ -81,6 +81,16 @@ impl CallStack {
         CallStack { frames: Vec::new() }
     }
 
+    /// Rebuild a call stack from its frames, outermost first.
+    pub fn from_frames(frames: Vec<StackFrame>) -> CallStack {
+        CallStack { frames }
+    }
+
+    /// The stack frames, outermost first.
+    pub fn frames(&self) -> &[StackFrame] {
+        &self.frames
+    }
+
     pub fn depth(&self) -> usize {
         self.frames.len()
     }
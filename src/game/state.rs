@@ -1,23 +1,52 @@
 use std::cmp::min;
-use std::collections::VecDeque;
+use std::fs;
+use std::mem;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 use std::vec::Vec;
 
 use crate::game::Result;
 use rand::{rngs::StdRng, SeedableRng};
-use tracing::debug;
+use tracing::{debug, warn};
 
+use crate::game::auxiliary;
+use crate::game::command_file::{CommandReader, CommandWriter, RecordedKey};
 use crate::game::error::GameError;
+use crate::game::graphics::{self, Graphics};
 use crate::game::instruction::{
     Form, Instruction, InstructionSet, OpCode, Operand, OperandSet, Result as InstructionResult,
 };
 use crate::game::memory::Memory;
+use crate::game::meta_command::{self, Input, MetaCommand};
+use crate::game::options::Options;
+use crate::game::quetzal::SaveState;
+use crate::game::sound::{self, SoundBackend, Sounds};
 use crate::game::stack::{CallStack, StackFrame};
-use crate::interface::Interface;
+use crate::game::transcript::Transcript;
+use crate::game::undo::{Snapshot, UndoStack};
+use crate::game::InputCode;
+use crate::interface::font::{self, Font};
+use crate::interface::{Interface, LineInput};
+use crate::loader::BlorbLoader;
 
-struct UndoBufferEntry {
-    pub memory: Memory,
-    pub call_stack: CallStack,
-    pub rng: StdRng,
+/// A routine called periodically while the game waits for input.
+#[derive(Debug, Clone, Copy)]
+pub struct Interrupt {
+    pub interval: Duration,
+    /// The byte address of the routine.
+    pub routine: usize,
+}
+
+/// The result of reading input that an interrupt routine can cut short.
+pub enum TimedInput<T> {
+    /// The input was read in full.
+    Done(T),
+    /// The interrupt routine ended input early. Holds the input received so far.
+    Aborted(T),
+    /// The interrupt routine quit or restarted the game, or an interpreter command rewound it,
+    /// and the instruction must stop.
+    Halted(InstructionResult),
 }
 
 /// Represents the current state of play.
@@ -28,45 +57,170 @@ pub struct GameState<'a> {
     pub instruction_set: InstructionSet,
     pub interface: &'a mut dyn Interface,
     pub rng: StdRng,
+    options: Options,
     initial_memory: Memory,
     call_stack: CallStack,
-    undo_buffer: VecDeque<UndoBufferEntry>,
+    undo_stack: UndoStack,
+    /// Snapshots taken before each command is read, for the undo interpreter command.
+    command_snapshots: UndoStack,
+    /// The address of the store byte or branch data of the instruction being executed.
+    result_address: usize,
+    /// The command file for input stream 1.
+    command_reader: Option<CommandReader>,
+    /// Whether input is being read from the command file rather than the keyboard.
+    reading_commands: bool,
+    /// The command file for output stream 4, which records the player's input.
+    command_writer: Option<CommandWriter>,
+    /// Whether output stream 4 is selected.
+    recording_commands: bool,
+    /// The window that text is printed to.
+    active_window: u16,
+    /// Whether output stream 1 (the screen) is selected.
+    screen_output: bool,
+    /// Output stream 2. This is open whenever the transcript bit in the header is set.
+    transcript: Option<Transcript>,
+    /// The name of the transcript file, once the player has chosen one.
+    transcript_file: Option<String>,
+    /// The tables of the selected output stream 3s, innermost last.
+    memory_streams: Vec<usize>,
+    /// Whether anything has been printed to the screen since this was last reset.
+    output_printed: bool,
+    /// The pixel-based screen model, in version 6.
+    pub graphics: Option<Graphics>,
+    /// The font selected with `set_font`.
+    font: Font,
+    /// Where pictures and sounds come from.
+    resources: Option<Rc<BlorbLoader>>,
+    /// Sound effects, if the interpreter has been given a backend to play them with.
+    pub sounds: Option<Sounds>,
 }
 
 impl<'a> GameState<'a> {
-    pub fn new(data: Vec<u8>, interface: &'a mut dyn Interface) -> Result<GameState> {
+    pub fn new(
+        data: Vec<u8>,
+        interface: &'a mut dyn Interface,
+        options: Options,
+        resources: Option<Rc<BlorbLoader>>,
+    ) -> Result<GameState> {
         let mut memory = Memory::new(data);
         memory.validate_header()?;
+        let initial_memory = memory.clone();
         memory.set_general_headers();
         interface.set_z_machine_version(memory.version());
-        let (width, height) = interface.get_screen_size();
-        memory.set_screen_size(width, height);
-        Ok(GameState {
+        let graphics = if memory.version() == 6 {
+            let (width, height) = interface.get_screen_size();
+            Some(Graphics::new(
+                width.saturating_mul(graphics::FONT_WIDTH),
+                height.saturating_mul(graphics::FONT_HEIGHT),
+                resources.clone(),
+                options.frames.clone(),
+            )?)
+        } else {
+            None
+        };
+        let command_reader = options
+            .replay
+            .as_ref()
+            .map(CommandReader::open)
+            .transpose()?;
+        let command_writer = options
+            .record
+            .as_ref()
+            .map(CommandWriter::create)
+            .transpose()?;
+        let mut state = GameState {
             checksum_valid: memory.verify(),
             version: memory.version(),
             instruction_set: InstructionSet::new(memory.version()),
             call_stack: CallStack::new(),
-            undo_buffer: VecDeque::new(),
+            undo_stack: UndoStack::new(options.undo_depth),
+            command_snapshots: UndoStack::new(options.undo_depth),
             rng: StdRng::from_entropy(),
-            initial_memory: memory.clone(),
+            options,
+            initial_memory,
             memory,
             interface,
-        })
+            result_address: 0,
+            reading_commands: command_reader.is_some(),
+            command_reader,
+            recording_commands: command_writer.is_some(),
+            command_writer,
+            active_window: 0,
+            screen_output: true,
+            transcript: None,
+            transcript_file: None,
+            memory_streams: Vec::new(),
+            output_printed: false,
+            graphics,
+            font: Font::Normal,
+            resources,
+            sounds: None,
+        };
+        state.set_interpreter_headers();
+        Ok(state)
     }
 
-    /// Start the game
+    /// Play sound effects with a backend. Without one, the game is told that sounds aren't
+    /// available.
+    pub fn set_sound_backend(&mut self, backend: Box<dyn SoundBackend>) {
+        self.sounds = Some(Sounds::new(backend, self.resources.clone()));
+        self.set_interpreter_headers();
+    }
+
+    /// Set the headers describing the screen, its fonts and what it can show and play.
+    fn set_interpreter_headers(&mut self) {
+        let sounds_available = self.sounds.as_ref().is_some_and(Sounds::available);
+        self.memory.set_sounds_available(sounds_available);
+        self.memory.set_undo_available(self.undo_stack.enabled());
+        self.memory
+            .set_mouse_available(self.interface.mouse_available());
+        match &self.graphics {
+            Some(graphics) => {
+                let (width, height) = graphics.size();
+                self.memory.set_screen_size(width, height);
+                self.memory
+                    .set_font_size(graphics::FONT_WIDTH as u8, graphics::FONT_HEIGHT as u8);
+                self.memory
+                    .set_pictures_available(graphics.picture_count() > 0);
+            }
+            None => {
+                let (width, height) = self.interface.get_screen_size();
+                self.memory.set_screen_size(width, height);
+                self.memory.set_font_size(1, 1);
+            }
+        }
+    }
+
+    /// Start executing the game from the beginning. Version 6 starts by calling the main routine;
+    /// earlier versions start at an address with no routine around it.
+    fn enter_main(&mut self) -> Result<()> {
+        let start = self.memory.program_counter_starts().into();
+        if self.version == 6 {
+            let address = self.memory.unpack_routine_address(start);
+            self.invoke(address, None, None)
+        } else {
+            self.call_stack
+                .push(StackFrame::new(start, Vec::new(), 0, None));
+            Ok(())
+        }
+    }
+
+    /// Start the game. Running out of input quits it, like the quit instruction.
     pub fn run(&mut self) -> Result<()> {
         self.interface.init()?;
-        self.call_stack.push(StackFrame::new(
-            self.memory.program_counter_starts().into(),
-            Vec::new(),
-            0,
-            None,
-        ));
+        match self.run_until_quit() {
+            Err(e) if e.is_end_of_input() => {}
+            result => result?,
+        }
+        self.present_screen()
+    }
+
+    fn run_until_quit(&mut self) -> Result<()> {
+        self.enter_main()?;
         loop {
             match self.next_op()? {
                 InstructionResult::Continue => {}
-                InstructionResult::Restart => self.restart(),
+                InstructionResult::Restart => self.restart()?,
                 InstructionResult::Quit => return Ok(()),
                 InstructionResult::Return(result) => self.return_with(result)?,
                 InstructionResult::Invoke {
@@ -75,9 +229,27 @@ impl<'a> GameState<'a> {
                     arguments,
                 } => self.invoke(address, store_to, arguments)?,
             }
+            match self.poll_sounds()? {
+                Some(InstructionResult::Restart) => self.restart()?,
+                Some(_) => return Ok(()),
+                None => {}
+            }
         }
     }
 
+    /// Call the routine of a sound effect that has finished, if there is one. Its result is
+    /// thrown away, unless it quit or restarted the game.
+    fn poll_sounds(&mut self) -> Result<Option<InstructionResult>> {
+        let routine = match self.sounds.as_mut().and_then(Sounds::poll) {
+            Some(routine) => routine,
+            None => return Ok(None),
+        };
+        Ok(match self.run_routine(routine)? {
+            result @ (InstructionResult::Quit | InstructionResult::Restart) => Some(result),
+            _ => None,
+        })
+    }
+
     pub fn frame_id(&self) -> u16 {
         self.call_stack.depth() as u16
     }
@@ -91,45 +263,923 @@ impl<'a> GameState<'a> {
         self.call_stack.frame()
     }
 
+    /// Save the game state to the undo stack. The variable is set to 1 now, and to 2 when the
+    /// state is restored; if undo isn't available, it's set to -1.
     pub fn save_undo(&mut self, restore_flag: u8) {
-        if self.undo_buffer.len() >= 10 {
-            self.undo_buffer.pop_front();
+        if !self.undo_stack.enabled() {
+            self.set_variable(restore_flag, -1i16 as u16);
+            return;
         }
         self.set_variable(restore_flag, 2);
-        self.undo_buffer.push_front(UndoBufferEntry {
-            memory: self.memory.clone(),
-            call_stack: self.call_stack.clone(),
-            rng: self.rng.clone(),
-        });
+        let snapshot = self.snapshot();
+        self.undo_stack.push(snapshot);
         self.poke_variable(restore_flag, 1).unwrap();
     }
 
-    pub fn restore_undo(&mut self) -> bool {
-        if let Some(buffer) = self.undo_buffer.pop_back() {
-            self.memory = buffer.memory;
-            self.call_stack = buffer.call_stack;
-            self.rng = buffer.rng;
-            true
+    /// Restore the most recently saved undo state. Returns false if there isn't one.
+    pub fn restore_undo(&mut self) -> Result<bool> {
+        match self.undo_stack.pop() {
+            Some(snapshot) => {
+                self.restore_snapshot(snapshot)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot::new(
+            &self.memory,
+            &self.initial_memory,
+            &self.call_stack,
+            &self.rng,
+        )
+    }
+
+    fn restore_snapshot(&mut self, snapshot: Snapshot) -> Result<()> {
+        snapshot.restore_memory(&mut self.memory, &self.initial_memory)?;
+        self.call_stack = snapshot.call_stack;
+        self.rng = snapshot.rng;
+        Ok(())
+    }
+
+    /// Go back to the state before the last command, for the undo interpreter command.
+    /// Returns false if there's no earlier command to go back to.
+    fn undo_command(&mut self) -> Result<bool> {
+        // The newest snapshot was taken as the current command was read.
+        let current = self.command_snapshots.pop();
+        match (current, self.command_snapshots.pop()) {
+            (Some(_), Some(previous)) => {
+                self.restore_snapshot(previous)?;
+                self.print("[Previous turn undone.]\n")?;
+                Ok(true)
+            }
+            (current, _) => {
+                if let Some(current) = current {
+                    self.command_snapshots.push(current);
+                }
+                self.print("[You can't undo any further.]\n")?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Print text to the selected output streams. While output stream 3 is selected, text goes
+    /// only to its table.
+    pub fn print(&mut self, text: &str) -> Result<()> {
+        if let Some(&table) = self.memory_streams.last() {
+            return self.memory.write_to_table(table, text);
+        }
+        self.sync_transcript()?;
+        if self.screen_output {
+            let shown;
+            let shown = if self.font == Font::CharacterGraphics {
+                shown = self.character_graphics(text);
+                &shown
+            } else {
+                text
+            };
+            self.interface.print(shown)?;
+            if let Some(graphics) = &mut self.graphics {
+                graphics.print(self.active_window, shown)?;
+            }
+            self.output_printed = true;
+        }
+        if self.active_window == 0 {
+            if let Some(transcript) = &mut self.transcript {
+                transcript.write(text)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Convert text to the characters of font 3, falling back to ASCII for any the interface
+    /// can't show.
+    fn character_graphics(&self, text: &str) -> String {
+        text.chars()
+            .map(|c| match font::character_graphic(c) {
+                shape if shape != c && !self.interface.char_support(shape).output => {
+                    font::character_graphic_ascii(shape)
+                }
+                shape => shape,
+            })
+            .collect()
+    }
+
+    /// Select a font by number, returning the number of the previous font, or 0 if the font
+    /// isn't available (in which case the font doesn't change). Font 0 selects nothing, and
+    /// returns the current font.
+    pub fn set_font(&mut self, number: u16) -> u16 {
+        if number == 0 {
+            return self.font.number();
+        }
+        match Font::from_number(number).filter(Font::available) {
+            Some(font) => mem::replace(&mut self.font, font).number(),
+            None => 0,
+        }
+    }
+
+    /// Print a single character to the selected output streams.
+    pub fn print_char(&mut self, c: char) -> Result<()> {
+        self.print(c.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Make the given window the active window.
+    pub fn set_window(&mut self, window: u16) -> Result<()> {
+        self.active_window = window;
+        // The interface only has two windows. In version 6, every window other than the main
+        // one is shown in the upper window.
+        match self.graphics {
+            Some(_) => self.interface.set_active(window.min(1)),
+            None => self.interface.set_active(window),
+        }
+    }
+
+    /// The window that text is printed to.
+    pub fn active_window(&self) -> u16 {
+        self.active_window
+    }
+
+    /// The version 6 screen model.
+    pub fn graphics(&mut self) -> Result<&mut Graphics> {
+        self.graphics
+            .as_mut()
+            .ok_or_else(|| GameError::invalid_operation("Graphics are only available in version 6"))
+    }
+
+    /// Show the version 6 screen, if it has changed.
+    pub fn present_screen(&mut self) -> Result<()> {
+        match &mut self.graphics {
+            Some(graphics) => graphics.present(),
+            None => Ok(()),
+        }
+    }
+
+    /// Prepare, start, stop or unload a sound effect. Sounds 1 and 2 are the built-in high and
+    /// low bleeps, and stopping sound 0 stops whatever is playing. The volume runs from 1 to 8
+    /// (255 meaning as loud as possible), a repeat count of 0 plays the sound as often as its
+    /// resource says, and the routine is the packed address of one to call when the sound
+    /// finishes, or 0.
+    pub fn sound_effect(
+        &mut self,
+        number: u16,
+        effect: u16,
+        volume: u16,
+        repeats: u8,
+        routine: u16,
+    ) -> Result<()> {
+        let sounds = match &mut self.sounds {
+            Some(sounds) => sounds,
+            None => return Ok(()),
+        };
+        if matches!(number, 1 | 2) && effect != 3 && effect != 4 {
+            return sounds.bleep(number != 2);
+        }
+        let routine = match routine {
+            0 => None,
+            packed => Some(self.memory.unpack_routine_address(packed.into())),
+        };
+        match effect {
+            // Sounds are already in memory, so there's nothing to prepare or unload beyond
+            // stopping them.
+            1 => Ok(()),
+            2 => {
+                let volume = match volume {
+                    1..=8 => volume as u8,
+                    _ => 8,
+                };
+                sounds.start(number, volume, repeats, routine)
+            }
+            3 => sounds.stop(number),
+            4 => sounds.finish_with(number),
+            _ => {
+                warn!("Unknown sound effect {}", effect);
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether printed text is going to the upper window on screen (rather than to the lower
+    /// window or output stream 3).
+    pub fn printing_to_upper_window(&self) -> bool {
+        self.active_window == 1 && self.memory_streams.is_empty()
+    }
+
+    /// Select or deselect an output stream. Selecting stream 3 requires the table to write to.
+    pub fn select_output_stream(
+        &mut self,
+        stream: u16,
+        enable: bool,
+        table: Option<u16>,
+    ) -> Result<()> {
+        match (stream, enable) {
+            (1, _) => self.screen_output = enable,
+            (2, _) => {
+                self.memory.set_transcribing(enable);
+                self.sync_transcript()?;
+            }
+            (3, true) => {
+                let table = table.ok_or_else(|| {
+                    GameError::invalid_operation("Output stream 3 selected without a table")
+                })? as usize;
+                if table + 2 > self.memory.dynamic_memory().len() {
+                    return Err(GameError::invalid_operation(
+                        "Output stream 3 table is not in dynamic memory",
+                    ));
+                }
+                if self.memory_streams.len() >= 16 {
+                    return Err(GameError::invalid_operation(
+                        "Output stream 3 nested more than 16 levels deep",
+                    ));
+                }
+                self.memory.set_word(table, 0);
+                self.memory_streams.push(table);
+            }
+            (3, false) => {
+                if self.memory_streams.pop().is_none() {
+                    warn!("Output stream 3 deselected when it was not selected");
+                }
+            }
+            (4, true) => {
+                if self.command_writer.is_none() {
+                    let default = format!("{}.rec", self.options.story_name);
+                    let file_name = self.prompt_file_name(&default)?;
+                    match CommandWriter::create(&file_name) {
+                        Ok(writer) => self.command_writer = Some(writer),
+                        Err(e) => {
+                            warn!("Could not create command file {}: {}", file_name, e);
+                            return Ok(());
+                        }
+                    }
+                }
+                self.recording_commands = true;
+            }
+            (4, false) => self.recording_commands = false,
+            _ => warn!("Output stream {} is not supported", stream),
+        }
+        Ok(())
+    }
+
+    /// Open or close the transcript to match the transcript bit in the header, which the game
+    /// may change at any time.
+    fn sync_transcript(&mut self) -> Result<()> {
+        let transcribing = self.memory.transcribing();
+        if transcribing && self.transcript.is_none() {
+            let file_name = match self.transcript_file.take() {
+                Some(file_name) => file_name,
+                None => {
+                    let default = format!("{}.txt", self.options.story_name);
+                    self.prompt_file_name(&default)?
+                }
+            };
+            match Transcript::open(&file_name) {
+                Ok(transcript) => {
+                    self.transcript = Some(transcript);
+                    self.transcript_file = Some(file_name);
+                }
+                Err(e) => {
+                    warn!("Could not open transcript file {}: {}", file_name, e);
+                    self.memory.set_transcribing(false);
+                }
+            }
+        } else if !transcribing {
+            self.transcript = None;
+        }
+        Ok(())
+    }
+
+    /// Redraw the status line, using the location, score and moves (or hours and minutes)
+    /// held in the first three global variables.
+    pub fn show_status(&mut self) -> Result<()> {
+        if self.version > 3 {
+            return Ok(());
+        }
+        let location = match self.memory.get_global(0) {
+            0 => String::new(),
+            object => self.memory.object_short_name(object)?,
+        };
+        let (first, second) = (self.memory.get_global(1), self.memory.get_global(2));
+        let progress = if self.memory.status_line_shows_time() {
+            let suffix = if first < 12 { "am" } else { "pm" };
+            format!("Time: {}:{:02} {}", (first + 11) % 12 + 1, second, suffix)
+        } else {
+            format!("Score: {}  Moves: {}", first as i16, second)
+        };
+        self.interface.show_status(&location, &progress)
+    }
+
+    /// Work out the interrupt routine for a read instruction from its `time` and `routine`
+    /// operands, if it has one.
+    pub fn interrupt(&self, time: Option<u16>, routine: Option<u16>) -> Option<Interrupt> {
+        match (time, routine) {
+            (Some(time), Some(routine)) if time != 0 && routine != 0 => Some(Interrupt {
+                interval: Duration::from_millis(time as u64 * 100),
+                routine: self.memory.unpack_routine_address(routine as usize),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Read a command for `sread` or `aread`. Interpreter commands are handled here and never
+    /// reach the game, nor the transcript or the command file being recorded. Those that change
+    /// the state of the game (such as undo and restore) return `Halted`, so that the game
+    /// carries on from its new state.
+    pub fn read_command(
+        &mut self,
+        max_chars: usize,
+        terminators: &[InputCode],
+        interrupt: Option<Interrupt>,
+    ) -> Result<TimedInput<(String, u8)>> {
+        loop {
+            let (line, terminator) =
+                match self.read_line_timed(max_chars, terminators, interrupt)? {
+                    TimedInput::Done(input) => input,
+                    input => return Ok(input),
+                };
+            match meta_command::parse(&line, &self.options.command_prefix) {
+                Input::Game(command) => {
+                    // The line is recorded as typed, so that it means the same when replayed.
+                    self.record_input(&line)?;
+                    return Ok(TimedInput::Done((command, terminator)));
+                }
+                Input::Command(command) => {
+                    if let Some(result) = self.run_meta_command(command)? {
+                        return Ok(TimedInput::Halted(result));
+                    }
+                }
+                Input::Invalid(message) => self.print(&format!("[{}]\n", message))?,
+            }
+        }
+    }
+
+    /// Carry out an interpreter command. Returns the result to end the reading instruction
+    /// with, or None to read another line.
+    fn run_meta_command(&mut self, command: MetaCommand) -> Result<Option<InstructionResult>> {
+        match command {
+            MetaCommand::Undo => Ok(self.undo_command()?.then_some(InstructionResult::Continue)),
+            MetaCommand::Restore => match self.restore()? {
+                Some(result) => {
+                    self.print("[Restored.]\n")?;
+                    Ok(Some(result))
+                }
+                None => {
+                    self.print("[Restore failed.]\n")?;
+                    Ok(None)
+                }
+            },
+            MetaCommand::Transcript(on) => {
+                self.select_output_stream(2, on, None)?;
+                if self.memory.transcribing() {
+                    self.print("[Transcript on.]\n")?;
+                } else {
+                    self.print("[Transcript off.]\n")?;
+                }
+                Ok(None)
+            }
+            MetaCommand::Seed(seed) => {
+                self.rng = StdRng::seed_from_u64(seed);
+                self.print(&format!("[Random numbers seeded with {}.]\n", seed))?;
+                Ok(None)
+            }
+            MetaCommand::Quit => Ok(Some(InstructionResult::Quit)),
+            MetaCommand::Help => {
+                let help = meta_command::help(&self.options.command_prefix);
+                self.print(&help)?;
+                Ok(None)
+            }
+            MetaCommand::ObjectTree => {
+                let tree = self.object_tree()?;
+                self.print(&tree)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Describe the object tree, one object per line, with each object's children indented
+    /// below it.
+    fn object_tree(&self) -> Result<String> {
+        let count = self.memory.object_count();
+        let mut tree = String::new();
+        for root in (1..=count).filter(|&object| self.memory.object_parent(object) == 0) {
+            let mut objects = vec![(root, 0)];
+            while let Some((object, depth)) = objects.pop() {
+                tree.push_str(&format!(
+                    "{:indent$}[{}] {}\n",
+                    "",
+                    object,
+                    self.memory.object_short_name(object)?,
+                    indent = depth * 2
+                ));
+                // A broken tree could go round in circles, but no deeper than every object.
+                if depth >= count as usize {
+                    continue;
+                }
+                let mut children = Vec::new();
+                let mut child = self.memory.object_child(object);
+                while child != 0 && child <= count && children.len() < count as usize {
+                    children.push((child, depth + 1));
+                    child = self.memory.object_sibling(child);
+                }
+                objects.extend(children.into_iter().rev());
+            }
+        }
+        Ok(tree)
+    }
+
+    /// Read a line of input from the current input stream, calling the interrupt routine (if
+    /// there is one) whenever its interval passes. The line ends with Enter or one of the
+    /// terminating keys, and comes with the ZSCII code of the key that ended it (0 if the
+    /// interrupt routine did). The line isn't recorded anywhere.
+    pub fn read_line_timed(
+        &mut self,
+        max_chars: usize,
+        terminators: &[InputCode],
+        interrupt: Option<Interrupt>,
+    ) -> Result<TimedInput<(String, u8)>> {
+        self.present_screen()?;
+        let input = match (self.next_command()?, interrupt) {
+            (Some(line), _) => {
+                let line: String = line.chars().take(max_chars).collect();
+                // Show the command as though the player had typed it.
+                self.interface.print(&line)?;
+                self.interface.print("\n")?;
+                TimedInput::Done((line, 13))
+            }
+            (None, None) if !self.sound_waiting() => {
+                let (line, terminator) = self.interface.read_line(max_chars, terminators)?;
+                TimedInput::Done((line, self.terminator_zscii(terminator)?))
+            }
+            (None, interrupt) => self.read_line_interrupted(max_chars, terminators, interrupt)?,
+        };
+        Ok(input)
+    }
+
+    /// The keys that end a line of input for `aread` as well as Enter, from the story's table of
+    /// terminating characters.
+    pub fn terminating_keys(&self) -> Vec<InputCode> {
+        let table = self.memory.terminating_characters();
+        InputCode::terminating_keys()
+            .filter(|&key| match self.memory.zscii_from_code(key) {
+                Ok(zscii) => table.contains(&255) || table.contains(&zscii),
+                Err(_) => false,
+            })
+            .collect()
+    }
+
+    /// The ZSCII code of the key that ended a line of input.
+    fn terminator_zscii(&mut self, terminator: InputCode) -> Result<u8> {
+        self.note_click(terminator);
+        self.memory.zscii_from_code(terminator)
+    }
+
+    /// If the input was a mouse click, tell the game where it was.
+    fn note_click(&mut self, input: InputCode) {
+        if let InputCode::SingleClick | InputCode::DoubleClick = input {
+            let (x, y) = self.mouse_position();
+            self.memory.set_mouse_position(x, y);
+        }
+    }
+
+    /// Where the mouse was last clicked, in the units the game uses: pixels in version 6, and
+    /// characters otherwise.
+    pub fn mouse_position(&self) -> (u16, u16) {
+        let (column, line) = self.interface.mouse_position();
+        match self.graphics {
+            // The click is somewhere inside a character cell.
+            Some(_) => (
+                (column - 1) * graphics::FONT_WIDTH + 1,
+                (line - 1) * graphics::FONT_HEIGHT + 1,
+            ),
+            None => (column, line),
+        }
+    }
+
+    /// Which mouse buttons are held down, with bit 0 for the leftmost button.
+    pub fn mouse_buttons(&self) -> u16 {
+        self.interface.mouse_buttons()
+    }
+
+    /// Write a line of input to the command file being recorded, and to the transcript.
+    fn record_input(&mut self, line: &str) -> Result<()> {
+        if let Some(writer) = self
+            .command_writer
+            .as_mut()
+            .filter(|_| self.recording_commands)
+        {
+            writer.write_line(line)?;
+        }
+        if let Some(transcript) = &mut self.transcript {
+            transcript.write(line)?;
+            transcript.write("\n")?;
+        }
+        Ok(())
+    }
+
+    /// Whether a sound's routine is waiting for it to finish, so that input has to stop every so
+    /// often to check.
+    fn sound_waiting(&self) -> bool {
+        self.sounds.as_ref().is_some_and(Sounds::waiting)
+    }
+
+    /// How long to wait for input before checking on the interrupt routine (if there is one)
+    /// and any sound that's playing.
+    fn input_timeout(next_interrupt: Option<Instant>) -> Duration {
+        match next_interrupt {
+            Some(at) => min(
+                at.saturating_duration_since(Instant::now()),
+                sound::POLL_INTERVAL,
+            ),
+            None => sound::POLL_INTERVAL,
+        }
+    }
+
+    /// Run the routines that are due while waiting for input: that of a sound effect that has
+    /// finished, then the interrupt routine, if its interval has passed. Returns None to carry
+    /// on waiting, or the result that ends the input.
+    fn run_input_routines(
+        &mut self,
+        interrupt: Option<Interrupt>,
+        next_interrupt: &mut Option<Instant>,
+    ) -> Result<Option<InstructionResult>> {
+        if let Some(result) = self.poll_sounds()? {
+            return Ok(Some(result));
+        }
+        match (interrupt, *next_interrupt) {
+            (Some(interrupt), Some(at)) if Instant::now() >= at => {
+                let result = self.run_routine(interrupt.routine)?;
+                *next_interrupt = Some(Instant::now() + interrupt.interval);
+                Ok(match result {
+                    InstructionResult::Return(0) => None,
+                    result => Some(result),
+                })
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn read_line_interrupted(
+        &mut self,
+        max_chars: usize,
+        terminators: &[InputCode],
+        interrupt: Option<Interrupt>,
+    ) -> Result<TimedInput<(String, u8)>> {
+        let mut typed = String::new();
+        let mut next_interrupt = interrupt.map(|interrupt| Instant::now() + interrupt.interval);
+        loop {
+            match self.interface.read_line_timed(
+                max_chars,
+                &typed,
+                Self::input_timeout(next_interrupt),
+                terminators,
+            )? {
+                LineInput::Done(line, terminator) => {
+                    return Ok(TimedInput::Done((line, self.terminator_zscii(terminator)?)))
+                }
+                LineInput::Timeout(line) => typed = line,
+            }
+
+            self.output_printed = false;
+            match self.run_input_routines(interrupt, &mut next_interrupt)? {
+                None => {}
+                Some(InstructionResult::Return(_)) => return Ok(TimedInput::Aborted((typed, 0))),
+                Some(result) => return Ok(TimedInput::Halted(result)),
+            }
+            // The routines' output interrupted the input line, so show it again.
+            if self.output_printed {
+                self.interface.print(&typed)?;
+            }
+        }
+    }
+
+    /// Read a single keypress from the current input stream, as a ZSCII code. If there is an
+    /// interrupt routine, it is called whenever its interval passes.
+    pub fn read_key(&mut self, interrupt: Option<Interrupt>) -> Result<TimedInput<u8>> {
+        self.present_screen()?;
+        let key = match self
+            .command_reader
+            .as_mut()
+            .filter(|_| self.reading_commands)
+        {
+            Some(reader) => reader.read_key()?,
+            None => None,
+        };
+        let zscii = match key {
+            Some(RecordedKey::Zscii(zscii)) => zscii,
+            Some(RecordedKey::Character(c)) => self
+                .memory
+                .zscii_from_code(InputCode::Character(c))
+                .unwrap_or(b'?'),
+            None => {
+                self.stop_reading_commands();
+                let timed = interrupt.is_some() || self.sound_waiting();
+                let mut next_interrupt =
+                    interrupt.map(|interrupt| Instant::now() + interrupt.interval);
+                loop {
+                    let input = if timed {
+                        match self
+                            .interface
+                            .read_char_timed(Self::input_timeout(next_interrupt))?
+                        {
+                            Some(input) => input,
+                            None => {
+                                match self.run_input_routines(interrupt, &mut next_interrupt)? {
+                                    None => continue,
+                                    Some(InstructionResult::Return(_)) => {
+                                        return Ok(TimedInput::Aborted(0))
+                                    }
+                                    Some(result) => return Ok(TimedInput::Halted(result)),
+                                }
+                            }
+                        }
+                    } else {
+                        self.interface.read_char()?
+                    };
+                    self.note_click(input);
+                    // Keys with no ZSCII code, such as characters missing from the
+                    // translation table, are ignored.
+                    match self.memory.zscii_from_code(input) {
+                        Ok(zscii) => break zscii,
+                        Err(_) => warn!("Ignoring key with no ZSCII equivalent"),
+                    }
+                }
+            }
+        };
+        if let Some(writer) = self
+            .command_writer
+            .as_mut()
+            .filter(|_| self.recording_commands)
+        {
+            writer.write_key(zscii)?;
+        }
+        Ok(TimedInput::Done(zscii))
+    }
+
+    /// Read the next line from the command file, if input stream 1 is selected.
+    fn next_command(&mut self) -> Result<Option<String>> {
+        let line = match self
+            .command_reader
+            .as_mut()
+            .filter(|_| self.reading_commands)
+        {
+            Some(reader) => reader.read_line()?,
+            None => None,
+        };
+        if line.is_none() {
+            self.stop_reading_commands();
+        }
+        Ok(line)
+    }
+
+    /// Go back to reading from the keyboard once the command file runs out.
+    fn stop_reading_commands(&mut self) {
+        if self.reading_commands {
+            debug!("Reached the end of the command file");
+            self.reading_commands = false;
+            self.command_reader = None;
+        }
+    }
+
+    /// Select the input stream: 0 for the keyboard, or 1 for a command file. If no command
+    /// file is open, the player is asked for one.
+    pub fn select_input_stream(&mut self, stream: u16) -> Result<()> {
+        match stream {
+            0 => self.reading_commands = false,
+            1 => {
+                if self.command_reader.is_none() {
+                    let default = format!("{}.rec", self.options.story_name);
+                    let file_name = self.prompt_file_name(&default)?;
+                    match CommandReader::open(&file_name) {
+                        Ok(reader) => self.command_reader = Some(reader),
+                        Err(e) => {
+                            warn!("Could not open command file {}: {}", file_name, e);
+                            return Ok(());
+                        }
+                    }
+                }
+                self.reading_commands = true;
+            }
+            _ => {
+                return Err(GameError::invalid_operation(format!(
+                    "Invalid input stream {}",
+                    stream
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask the player for the name of a file, suggesting a default. The answer always comes
+    /// from the keyboard, and is never recorded: it isn't input for the game, and a command file
+    /// replayed later would get out of step if a prompt came up at a different time, or not at
+    /// all.
+    fn prompt_file_name(&mut self, default: &str) -> Result<String> {
+        self.interface
+            .print(&format!("Enter a file name (default is \"{}\"): ", default))?;
+        let (name, _) = self.interface.read_line(64, &[InputCode::Newline])?;
+        let name = name.trim();
+        Ok(if name.is_empty() {
+            default.to_string()
+        } else {
+            name.to_string()
+        })
+    }
+
+    /// Save the game to a Quetzal file chosen by the player. Returns whether the save succeeded.
+    pub fn save(&mut self) -> Result<bool> {
+        let default = format!("{}.qzl", self.options.story_name);
+        let file_name = self.prompt_file_name(&default)?;
+
+        let save_state = SaveState {
+            release_number: self.memory.release_number(),
+            serial_number: self.memory.serial_number(),
+            checksum: self.memory.checksum(),
+            pc: self.result_address,
+            dynamic_memory: self.memory.dynamic_memory().to_vec(),
+            call_stack: self.call_stack.clone(),
+        };
+        let data = save_state.to_bytes(self.initial_memory.dynamic_memory(), self.version);
+
+        if let Err(e) = fs::write(&file_name, data) {
+            warn!("Could not write save file {}: {}", file_name, e);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Work out the path of an auxiliary file from the name the game gave (a length byte followed
+    /// by the characters), asking the player to choose if prompting is on. Returns None if the
+    /// name can't be used.
+    fn auxiliary_path(&mut self, name: Option<usize>, prompt: bool) -> Result<Option<PathBuf>> {
+        let mut file_name = match name {
+            Some(name) => {
+                let length = self.memory.get_byte(name) as usize;
+                let name = self.memory.get_bytes(name + 1, length);
+                String::from_utf8_lossy(&name).into_owned()
+            }
+            None => self.options.story_name.clone(),
+        };
+        if prompt {
+            let default = auxiliary::file_name(&file_name).unwrap_or_default();
+            file_name = self.prompt_file_name(&default)?;
+        }
+        let file_name = match auxiliary::file_name(&file_name) {
+            Some(file_name) => file_name,
+            None => {
+                warn!("Invalid auxiliary file name {:?}", file_name);
+                return Ok(None);
+            }
+        };
+        let directory = match &self.options.auxiliary_directory {
+            Some(directory) => directory.clone(),
+            None => auxiliary::default_directory(&self.options.story_name),
+        };
+        Ok(Some(directory.join(file_name)))
+    }
+
+    /// Save a table of memory to an auxiliary file. Returns whether the save succeeded.
+    pub fn save_auxiliary(
+        &mut self,
+        table: usize,
+        length: usize,
+        name: Option<usize>,
+        prompt: bool,
+    ) -> Result<bool> {
+        let path = match self.auxiliary_path(name, prompt)? {
+            Some(path) => path,
+            None => return Ok(false),
+        };
+        if table + length > self.memory.dynamic_memory().len() {
+            return Err(GameError::invalid_operation(
+                "Auxiliary save table is not in dynamic memory",
+            ));
+        }
+        let data = self.memory.get_bytes(table, length);
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, data));
+        if let Err(e) = written {
+            warn!("Could not write auxiliary file {}: {}", path.display(), e);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Load an auxiliary file into a table of memory, reading no more than the length of the
+    /// table. Returns the number of bytes read, which is 0 if the restore failed.
+    pub fn restore_auxiliary(
+        &mut self,
+        table: usize,
+        length: usize,
+        name: Option<usize>,
+        prompt: bool,
+    ) -> Result<u16> {
+        let path = match self.auxiliary_path(name, prompt)? {
+            Some(path) => path,
+            None => return Ok(0),
+        };
+        if table + length > self.memory.dynamic_memory().len() {
+            return Err(GameError::invalid_operation(
+                "Auxiliary restore table is not in dynamic memory",
+            ));
+        }
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Could not read auxiliary file {}: {}", path.display(), e);
+                return Ok(0);
+            }
+        };
+        let data = &data[..data.len().min(length)];
+        self.memory.set_bytes(table, data);
+        Ok(data.len() as u16)
+    }
+
+    /// Restore the game from a Quetzal file chosen by the player. If successful, execution
+    /// resumes from the original save instruction, and the result of that instruction is
+    /// returned. Returns None if the restore failed.
+    pub fn restore(&mut self) -> Result<Option<InstructionResult>> {
+        let default = format!("{}.qzl", self.options.story_name);
+        let file_name = self.prompt_file_name(&default)?;
+
+        let data = match fs::read(&file_name) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Could not read save file {}: {}", file_name, e);
+                return Ok(None);
+            }
+        };
+
+        let save_state = match SaveState::from_bytes(
+            &data,
+            self.initial_memory.dynamic_memory(),
+            self.version,
+        ) {
+            Ok(save_state) => save_state,
+            Err(e) => {
+                warn!("Could not restore from {}: {}", file_name, e);
+                return Ok(None);
+            }
+        };
+
+        if save_state.release_number != self.memory.release_number()
+            || save_state.serial_number != self.memory.serial_number()
+            || save_state.checksum != self.memory.checksum()
+        {
+            warn!("Save file {} is for a different story", file_name);
+            return Ok(None);
+        }
+
+        // The transcription and fixed-pitch bits survive a restore.
+        let transcribing = self.memory.transcribing();
+        let force_fixed_font = self.memory.force_fixed_font();
+        self.memory.set_dynamic_memory(&save_state.dynamic_memory)?;
+        self.memory.set_general_headers();
+        self.set_interpreter_headers();
+        self.memory.set_transcribing(transcribing);
+        self.memory.set_force_fixed_font(force_fixed_font);
+        self.call_stack = save_state.call_stack;
+
+        // Complete the save instruction as though it had just succeeded.
+        let mut pc = save_state.pc;
+        if self.version <= 3 {
+            let expected = self.memory.get_byte(pc) >> 7 == 1;
+            let offset = self.branch_offset(&mut pc);
+            self.frame().pc = pc;
+            Ok(Some(
+                self.frame().conditional_branch(offset, true, expected),
+            ))
         } else {
-            false
+            let store_to = self.memory.read_byte(&mut pc);
+            self.frame().pc = pc;
+            self.set_variable(store_to, 2);
+            Ok(Some(InstructionResult::Continue))
         }
     }
 
-    fn restart(&mut self) {
+    fn restart(&mut self) -> Result<()> {
+        // Like a restore, a restart keeps the transcription and fixed-pitch bits.
+        let transcribing = self.memory.transcribing();
+        let force_fixed_font = self.memory.force_fixed_font();
         self.memory = self.initial_memory.clone();
         self.memory.set_general_headers();
-        let (width, height) = self.interface.get_screen_size();
-        self.memory.set_screen_size(width, height);
+        self.memory.set_transcribing(transcribing);
+        self.memory.set_force_fixed_font(force_fixed_font);
+        self.set_interpreter_headers();
         self.call_stack = CallStack::new();
-        self.undo_buffer = VecDeque::new();
+        self.undo_stack.clear();
+        self.command_snapshots.clear();
         self.rng = StdRng::from_entropy();
+        self.memory_streams.clear();
+        self.font = Font::Normal;
+        if let Some(graphics) = &mut self.graphics {
+            graphics.reset();
+        }
+        if let Some(sounds) = &mut self.sounds {
+            sounds.stop_all()?;
+        }
 
-        self.call_stack.push(StackFrame::new(
-            self.memory.program_counter_starts().into(),
-            Vec::new(),
-            0,
-            None,
-        ));
+        self.enter_main()
     }
 
     fn branch_offset(&self, pc: &mut usize) -> i16 {
@@ -228,6 +1278,15 @@ impl<'a> GameState<'a> {
             }
         }
 
+        // The undo interpreter command goes back to just before the instruction that read the
+        // command, so that it's executed again. The snapshot is taken before the instruction's
+        // operands are evaluated, since that can take them from the stack.
+        if op_code == OpCode::VarOp(4) {
+            let mut snapshot = self.snapshot();
+            snapshot.call_stack.frame().pc = instruction_pc;
+            self.command_snapshots.push(snapshot);
+        }
+
         self.call_stack.frame().pc = pc;
 
         let operands = OperandSet::new(operands);
@@ -245,6 +1304,7 @@ impl<'a> GameState<'a> {
                 f(self, operands)
             }
             Instruction::Branch(f, name) => {
+                self.result_address = pc;
                 let condition = self.memory.get_byte(pc) >> 7 == 1;
                 let offset = self.branch_offset(&mut pc);
                 debug!(
@@ -256,12 +1316,14 @@ impl<'a> GameState<'a> {
                 f(self, operands, condition, offset)
             }
             Instruction::Store(f, name) => {
+                self.result_address = pc;
                 let store_to = self.memory.read_byte(&mut pc);
                 debug!("{:x} {} {} >{:x}", instruction_pc, name, operands, store_to);
                 self.frame().pc = pc;
                 f(self, operands, store_to)
             }
             Instruction::BranchStore(f, name) => {
+                self.result_address = pc;
                 let store_to = self.memory.read_byte(&mut pc);
                 let condition = self.memory.get_byte(pc) >> 7 == 1;
 
@@ -330,24 +1392,27 @@ impl<'a> GameState<'a> {
         Ok(())
     }
 
-    /// Invoke an interupt routine and return the result of that routine.
-    pub fn run_routine(&mut self, address: u16) -> Result<Option<u16>> {
-        self.call_stack
-            .push(StackFrame::new(address as usize, Vec::new(), 0, None));
+    /// Run an interrupt routine (given by its byte address) until it returns. Returns
+    /// InstructionResult::Return with the routine's result, or Quit or Restart if the routine
+    /// ended the game.
+    pub fn run_routine(&mut self, address: usize) -> Result<InstructionResult> {
+        self.invoke(address, None, Some(Vec::new()))?;
 
         let starting_depth = self.call_stack.depth();
 
         loop {
+            if let Some(result) = self.poll_sounds()? {
+                return Ok(result);
+            }
             match self.next_op()? {
                 InstructionResult::Continue => {}
-                InstructionResult::Quit => return Ok(None),
-                InstructionResult::Restart => {
-                    self.restart();
-                    return Ok(None);
+                result @ (InstructionResult::Quit | InstructionResult::Restart) => {
+                    return Ok(result)
                 }
                 InstructionResult::Return(result) => {
                     if self.call_stack.depth() == starting_depth {
-                        return Ok(Some(result));
+                        self.call_stack.pop()?;
+                        return Ok(InstructionResult::Return(result));
                     } else {
                         self.return_with(result)?;
                     }
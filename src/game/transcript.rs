@@ -0,0 +1,26 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, prelude::*, BufWriter};
+use std::path::Path;
+
+/// A transcript of the game (output stream 2), holding everything printed to the lower window
+/// and every line typed by the player.
+pub struct Transcript {
+    file: BufWriter<File>,
+}
+
+impl Transcript {
+    /// Open a transcript file. If the file already exists, the transcript is added to the end.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Transcript> {
+        Ok(Transcript {
+            file: BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?),
+        })
+    }
+
+    pub fn write(&mut self, text: &str) -> io::Result<()> {
+        self.file.write_all(text.as_bytes())?;
+        if text.contains('\n') {
+            self.file.flush()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+//! The undo stack used by `save_undo` and `restore_undo`.
+//!
+//! Only dynamic memory changes during play, and usually only a little of it, so each snapshot
+//! keeps just the difference from the story's original dynamic memory, compressed as for a
+//! Quetzal `CMem` chunk.
+
+use std::collections::VecDeque;
+
+use rand::rngs::StdRng;
+
+use crate::game::memory::Memory;
+use crate::game::quetzal;
+use crate::game::stack::CallStack;
+use crate::game::Result;
+
+/// The number of snapshots kept if no depth is given.
+pub const DEFAULT_DEPTH: usize = 100;
+
+/// The state of the game at one point, to return to later.
+pub struct Snapshot {
+    /// The dynamic memory, compressed against the original.
+    memory: Vec<u8>,
+    pub call_stack: CallStack,
+    pub rng: StdRng,
+}
+
+impl Snapshot {
+    pub fn new(
+        memory: &Memory,
+        original: &Memory,
+        call_stack: &CallStack,
+        rng: &StdRng,
+    ) -> Snapshot {
+        Snapshot {
+            memory: quetzal::compress_memory(memory.dynamic_memory(), original.dynamic_memory()),
+            call_stack: call_stack.clone(),
+            rng: rng.clone(),
+        }
+    }
+
+    /// Put the snapshot's dynamic memory back into memory.
+    pub fn restore_memory(&self, memory: &mut Memory, original: &Memory) -> Result<()> {
+        let dynamic_memory = quetzal::decompress_memory(&self.memory, original.dynamic_memory())?;
+        memory.set_dynamic_memory(&dynamic_memory)
+    }
+}
+
+/// Snapshots, newest last. Once the stack is full, the oldest snapshot is dropped to make room
+/// for each new one.
+pub struct UndoStack {
+    depth: usize,
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl UndoStack {
+    pub fn new(depth: usize) -> UndoStack {
+        UndoStack {
+            depth,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    /// Whether snapshots can be kept at all.
+    pub fn enabled(&self) -> bool {
+        self.depth > 0
+    }
+
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if !self.enabled() {
+            return;
+        }
+        if self.snapshots.len() >= self.depth {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// Take the newest snapshot.
+    pub fn pop(&mut self) -> Option<Snapshot> {
+        self.snapshots.pop_back()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn memory(marker: u8) -> Memory {
+        let mut data = vec![0; 0x80];
+        // Static memory starts at 0x80, so all of this is dynamic.
+        data[0x0F] = 0x80;
+        data[0x40] = marker;
+        Memory::new(data)
+    }
+
+    /// A snapshot whose memory is marked, so it can be told apart from the others.
+    fn snapshot(marker: u8) -> Snapshot {
+        Snapshot::new(
+            &memory(marker),
+            &memory(0),
+            &CallStack::new(),
+            &StdRng::seed_from_u64(0),
+        )
+    }
+
+    /// The marker in a snapshot's memory.
+    fn marker(snapshot: &Snapshot) -> u8 {
+        let mut restored = memory(0);
+        snapshot.restore_memory(&mut restored, &memory(0)).unwrap();
+        restored.get_byte(0x40)
+    }
+
+    #[test]
+    fn snapshots_are_popped_newest_first() {
+        let mut stack = UndoStack::new(3);
+        for n in 1..=3 {
+            stack.push(snapshot(n));
+        }
+        for n in (1..=3).rev() {
+            assert_eq!(stack.pop().as_ref().map(marker), Some(n));
+        }
+    }
+
+    #[test]
+    fn oldest_snapshot_is_dropped_when_full() {
+        let mut stack = UndoStack::new(2);
+        for n in 1..=3 {
+            stack.push(snapshot(n));
+        }
+        assert_eq!(stack.pop().as_ref().map(marker), Some(3));
+        assert_eq!(stack.pop().as_ref().map(marker), Some(2));
+        assert!(stack.pop().is_none());
+    }
+
+    #[test]
+    fn empty_stack_has_nothing_to_pop() {
+        let mut stack = UndoStack::new(DEFAULT_DEPTH);
+        assert!(stack.pop().is_none());
+        stack.push(snapshot(1));
+        stack.clear();
+        assert!(stack.pop().is_none());
+    }
+
+    #[test]
+    fn zero_depth_keeps_nothing() {
+        let mut stack = UndoStack::new(0);
+        assert!(!stack.enabled());
+        stack.push(snapshot(1));
+        assert!(stack.pop().is_none());
+    }
+}
@@ -1,7 +1,19 @@
+pub mod colour;
+pub mod dumb;
+pub mod font;
 pub mod screen;
 pub mod terminal;
 pub mod text_style;
+pub mod unicode;
+pub use colour::Colour;
+pub use dumb::DumbInterface;
+pub use font::Font;
 pub use terminal::TerminalInterface;
+pub use unicode::CharSupport;
+
+use std::time::Duration;
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use crate::game::Result;
 
@@ -14,6 +26,16 @@ pub enum ClearMode {
     Single(u16),
 }
 
+/// The result of reading a line of input with a timeout.
+#[derive(Debug, Clone)]
+pub enum LineInput {
+    /// The player finished the line with the given key: Enter, or one of the terminating keys
+    /// the game asked for.
+    Done(String, InputCode),
+    /// The timeout passed first. Holds the text typed so far.
+    Timeout(String),
+}
+
 /// The user interface. Responsible for both rendering the game and recieving input.
 pub trait Interface {
     /// Prepare the interface.
@@ -46,12 +68,46 @@ pub trait Interface {
     /// Remove all text styles
     fn text_style_clear(&mut self) -> Result<()>;
 
+    /// Set the text colours of the active window. A colour of None leaves it unchanged.
+    fn set_colour(&mut self, foreground: Option<Colour>, background: Option<Colour>) -> Result<()>;
+
+    /// Whether the interface can print the character, and whether the player can type it.
+    fn char_support(&self, c: char) -> CharSupport;
+
     fn set_z_machine_version(&mut self, version: u8);
 
-    fn read_line(&mut self, max_chars: usize) -> Result<String>;
+    /// Read a line of input, which ends when Enter or one of the terminating keys is pressed.
+    /// Returns the line and the key that ended it.
+    fn read_line(
+        &mut self,
+        max_chars: usize,
+        terminators: &[InputCode],
+    ) -> Result<(String, InputCode)>;
 
     fn read_char(&mut self) -> Result<InputCode>;
 
+    /// Read a line of input, giving up if it isn't finished before the timeout. Editing starts
+    /// from text that has already been typed (and is already on screen).
+    fn read_line_timed(
+        &mut self,
+        max_chars: usize,
+        initial: &str,
+        timeout: Duration,
+        terminators: &[InputCode],
+    ) -> Result<LineInput>;
+
+    /// Read a single keypress, or None if no key is pressed before the timeout.
+    fn read_char_timed(&mut self, timeout: Duration) -> Result<Option<InputCode>>;
+
+    /// Whether the player can click on the screen.
+    fn mouse_available(&self) -> bool;
+
+    /// Where the mouse was last clicked, as a 1-indexed (column, line).
+    fn mouse_position(&self) -> (u16, u16);
+
+    /// Which mouse buttons are held down, with bit 0 for the leftmost button.
+    fn mouse_buttons(&self) -> u16;
+
     fn split_screen(&mut self, split: u16) -> Result<()>;
 
     fn get_screen_size(&self) -> (u16, u16);
@@ -60,8 +116,40 @@ pub trait Interface {
 
     fn set_cursor(&mut self, line: u16, column: u16) -> Result<()>;
 
+    /// Returns the cursor position in the active window, as (line, column). Both are 1-indexed.
+    fn get_cursor(&mut self) -> Result<(u16, u16)>;
+
+    /// Erase from the cursor to the end of the line in the active window, without moving the
+    /// cursor.
+    fn erase_line(&mut self) -> Result<()>;
+
     fn buffer_mode(&mut self, enable: bool) -> Result<()>;
 
+    /// Draw the status line (versions 1-3), with the location on the left and the score or
+    /// time on the right.
+    fn show_status(&mut self, location: &str, progress: &str) -> Result<()>;
+
     /// Close the UI immediately.
     fn quit(&mut self);
 }
+
+/// Lay out a status line of the given width. The location is truncated if there isn't room
+/// for both halves.
+pub fn format_status_line(location: &str, progress: &str, width: usize) -> String {
+    let progress_width = progress.width();
+    let mut line = String::from(" ");
+    let mut used = 1;
+    for c in location.chars() {
+        let char_width = c.width().unwrap_or(0);
+        if used + char_width + progress_width + 2 > width {
+            break;
+        }
+        line.push(c);
+        used += char_width;
+    }
+    let padding = width.saturating_sub(used + progress_width + 1);
+    line.push_str(&" ".repeat(padding));
+    line.push_str(progress);
+    line.push(' ');
+    line
+}
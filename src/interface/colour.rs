@@ -0,0 +1,84 @@
+/// A text colour.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Colour {
+    /// The interface's own default colour.
+    #[default]
+    Default,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    LightGrey,
+    MediumGrey,
+    DarkGrey,
+    /// A 15-bit colour, with five bits each of red (lowest), green and blue (highest).
+    True(u16),
+}
+
+impl Colour {
+    /// The colour for a number in the standard palette, as used by `set_colour`. Returns None
+    /// for 0 (keep the current colour), and for colours that only make sense in version 6.
+    pub fn from_palette(number: u16) -> Option<Colour> {
+        Some(match number {
+            1 => Colour::Default,
+            2 => Colour::Black,
+            3 => Colour::Red,
+            4 => Colour::Green,
+            5 => Colour::Yellow,
+            6 => Colour::Blue,
+            7 => Colour::Magenta,
+            8 => Colour::Cyan,
+            9 => Colour::White,
+            10 => Colour::LightGrey,
+            11 => Colour::MediumGrey,
+            12 => Colour::DarkGrey,
+            _ => return None,
+        })
+    }
+
+    /// The colour for a value given to `set_true_colour`. Returns None for -2 (keep the current
+    /// colour), and for the other negative values, which only make sense in version 6.
+    pub fn from_true_colour(value: u16) -> Option<Colour> {
+        match value as i16 {
+            -1 => Some(Colour::Default),
+            0.. => Some(Colour::True(value)),
+            _ => None,
+        }
+    }
+
+    /// The colour as a 15-bit true colour. Returns None for the default colour.
+    pub fn true_colour(&self) -> Option<u16> {
+        // The true colour equivalents of the palette, from the standard's table.
+        Some(match *self {
+            Colour::Default => return None,
+            Colour::Black => 0x0000,
+            Colour::Red => 0x001D,
+            Colour::Green => 0x0340,
+            Colour::Yellow => 0x03BD,
+            Colour::Blue => 0x59A0,
+            Colour::Magenta => 0x7C1F,
+            Colour::Cyan => 0x77A0,
+            Colour::White => 0x7FFF,
+            Colour::LightGrey => 0x5AD6,
+            Colour::MediumGrey => 0x4631,
+            Colour::DarkGrey => 0x2D6B,
+            Colour::True(value) => value,
+        })
+    }
+
+    /// The red, green and blue components of the colour, each from 0 to 255. Returns None for
+    /// the default colour.
+    pub fn rgb(&self) -> Option<(u8, u8, u8)> {
+        let true_colour = self.true_colour()?;
+        // Scale each 5-bit component up to 8 bits, so that 31 becomes 255.
+        let component = |shift: u16| {
+            let value = ((true_colour >> shift) & 0x1F) as u8;
+            (value << 3) | (value >> 2)
+        };
+        Some((component(0), component(5), component(10)))
+    }
+}
@@ -0,0 +1,370 @@
+use std::io::{self, prelude::*, IsTerminal};
+use std::time::Duration;
+
+use crate::cli::UpperWindowMode;
+use crate::game::error::GameError;
+use crate::game::Result;
+use crate::interface::{
+    format_status_line, unicode, CharSupport, ClearMode, Colour, InputCode, Interface, LineInput,
+};
+
+/// A plain-text interface that reads commands line by line from stdin and writes to stdout.
+/// It needs no terminal, so it's suitable for scripting and automated testing. Input is never
+/// timed out, so timed input routines don't run.
+pub struct DumbInterface {
+    width: u16,
+    height: u16,
+    upper_window_mode: UpperWindowMode,
+    version: u8,
+    active_window: u16,
+    /// The content of the upper window, one row per line.
+    upper_window: Vec<Vec<char>>,
+    upper_window_changed: bool,
+    /// The status line (versions 1-3), drawn above the upper window.
+    status_line: Option<String>,
+    /// The text printed to the lower window since the last line break.
+    lower_line: String,
+    /// The cursor position in the upper window (zero-indexed line and column).
+    cursor: (usize, usize),
+    /// Whether to echo input, so it appears in the output when stdin is piped.
+    echo_input: bool,
+    stdout: io::Stdout,
+}
+
+impl DumbInterface {
+    pub fn new(width: u16, height: u16, upper_window_mode: UpperWindowMode) -> Self {
+        Self {
+            width,
+            height,
+            upper_window_mode,
+            version: 0,
+            active_window: 0,
+            upper_window: Vec::new(),
+            upper_window_changed: false,
+            status_line: None,
+            lower_line: String::new(),
+            cursor: (0, 0),
+            echo_input: !io::stdin().is_terminal(),
+            stdout: io::stdout(),
+        }
+    }
+
+    fn clear_upper_window(&mut self) {
+        for line in self.upper_window.iter_mut() {
+            line.fill(' ');
+        }
+        self.cursor = (0, 0);
+        self.upper_window_changed = true;
+    }
+
+    fn print_upper(&mut self, c: char) -> Result<()> {
+        match self.upper_window_mode {
+            UpperWindowMode::Hide => {}
+            UpperWindowMode::Inline => {
+                write!(self.stdout, "{}", c)?;
+            }
+            UpperWindowMode::Grid => {
+                let (line, column) = &mut self.cursor;
+                if c == '\n' {
+                    *line += 1;
+                    *column = 0;
+                } else if let Some(cell) = self
+                    .upper_window
+                    .get_mut(*line)
+                    .and_then(|l| l.get_mut(*column))
+                {
+                    *cell = c;
+                    *column += 1;
+                    self.upper_window_changed = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw the upper window if it has changed since it was last drawn.
+    fn draw_upper_window(&mut self) -> Result<()> {
+        if self.upper_window_mode != UpperWindowMode::Grid || !self.upper_window_changed {
+            return Ok(());
+        }
+        self.upper_window_changed = false;
+        // Draw the upper window on lines of its own, then reprint any partial line (usually the
+        // prompt) that was printed before it.
+        if !self.lower_line.is_empty() {
+            writeln!(self.stdout)?;
+        }
+        if let Some(status_line) = &self.status_line {
+            writeln!(self.stdout, "{}", status_line.trim_end())?;
+        }
+        for line in &self.upper_window {
+            let line: String = line.iter().collect();
+            writeln!(self.stdout, "{}", line.trim_end())?;
+        }
+        write!(self.stdout, "{}", self.lower_line)?;
+        Ok(())
+    }
+
+    fn print_lower(&mut self, text: &str) -> Result<()> {
+        match text.rfind('\n') {
+            Some(i) => self.lower_line = text[i + 1..].to_string(),
+            None => self.lower_line.push_str(text),
+        }
+        write!(self.stdout, "{}", text)?;
+        Ok(())
+    }
+
+    /// Read a line from stdin, without its line ending.
+    fn read_input(&mut self) -> Result<String> {
+        self.read_input_from(&mut io::stdin().lock())
+    }
+
+    /// Read a line of input, without its line ending. At the end of the input, there's nothing
+    /// more the player can do, so the game quits.
+    fn read_input_from(&mut self, input: &mut impl BufRead) -> Result<String> {
+        self.draw_upper_window()?;
+        self.stdout.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Err(GameError::end_of_input());
+        }
+        let line = line.trim_end_matches(['\n', '\r']).to_string();
+        self.lower_line.clear();
+        if self.echo_input {
+            writeln!(self.stdout, "{}", line)?;
+        }
+        Ok(line)
+    }
+}
+
+impl Interface for DumbInterface {
+    fn init(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn print(&mut self, text: &str) -> Result<()> {
+        if self.active_window == 1 {
+            for c in text.chars() {
+                self.print_upper(c)?;
+            }
+        } else {
+            self.print_lower(text)?;
+        }
+        Ok(())
+    }
+
+    fn print_char(&mut self, text: char) -> Result<()> {
+        if self.active_window == 1 {
+            self.print_upper(text)
+        } else {
+            self.print_lower(text.encode_utf8(&mut [0; 4]))
+        }
+    }
+
+    fn clear(&mut self, mode: ClearMode) -> Result<()> {
+        match mode {
+            ClearMode::FullUnsplit => {
+                self.upper_window.clear();
+                self.active_window = 0;
+            }
+            ClearMode::Full | ClearMode::Single(1) => self.clear_upper_window(),
+            ClearMode::Single(_) => {}
+        }
+        Ok(())
+    }
+
+    fn done(&mut self) -> Result<()> {
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    fn text_style_bold(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn text_style_emphasis(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn text_style_reverse(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn text_style_fixed(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn text_style_clear(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_colour(
+        &mut self,
+        _foreground: Option<Colour>,
+        _background: Option<Colour>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn char_support(&self, c: char) -> CharSupport {
+        unicode::char_support(c)
+    }
+
+    fn set_z_machine_version(&mut self, version: u8) {
+        self.version = version;
+    }
+
+    fn read_line(
+        &mut self,
+        max_chars: usize,
+        _terminators: &[InputCode],
+    ) -> Result<(String, InputCode)> {
+        let line = self.read_input()?;
+        Ok((line.chars().take(max_chars).collect(), InputCode::Newline))
+    }
+
+    fn read_char(&mut self) -> Result<InputCode> {
+        let line = self.read_input()?;
+        Ok(match line.chars().next() {
+            Some(c) => InputCode::Character(c),
+            None => InputCode::Newline,
+        })
+    }
+
+    fn read_line_timed(
+        &mut self,
+        max_chars: usize,
+        initial: &str,
+        _timeout: Duration,
+        _terminators: &[InputCode],
+    ) -> Result<LineInput> {
+        let line = initial.to_string() + &self.read_input()?;
+        Ok(LineInput::Done(
+            line.chars().take(max_chars).collect(),
+            InputCode::Newline,
+        ))
+    }
+
+    fn read_char_timed(&mut self, _timeout: Duration) -> Result<Option<InputCode>> {
+        self.read_char().map(Some)
+    }
+
+    fn mouse_available(&self) -> bool {
+        false
+    }
+
+    fn mouse_position(&self) -> (u16, u16) {
+        (1, 1)
+    }
+
+    fn mouse_buttons(&self) -> u16 {
+        0
+    }
+
+    fn split_screen(&mut self, split: u16) -> Result<()> {
+        self.upper_window
+            .resize(split as usize, vec![' '; self.width as usize]);
+        if self.version == 3 {
+            // In version 3, splitting the screen clears the upper window.
+            self.clear_upper_window();
+        }
+        if self.cursor.0 >= split as usize {
+            self.cursor = (0, 0);
+        }
+        Ok(())
+    }
+
+    fn get_screen_size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    fn set_active(&mut self, active: u16) -> Result<()> {
+        self.active_window = active;
+        if active == 1 {
+            self.cursor = (0, 0);
+        }
+        Ok(())
+    }
+
+    fn set_cursor(&mut self, line: u16, column: u16) -> Result<()> {
+        // Cursor positions are 1-indexed.
+        self.cursor = (
+            line.saturating_sub(1) as usize,
+            column.saturating_sub(1) as usize,
+        );
+        Ok(())
+    }
+
+    fn get_cursor(&mut self) -> Result<(u16, u16)> {
+        if self.active_window == 1 {
+            Ok((self.cursor.0 as u16 + 1, self.cursor.1 as u16 + 1))
+        } else {
+            // The lower window has no fixed size, so its cursor is always on the last line.
+            let column = self.lower_line.chars().count() as u16 + 1;
+            Ok((self.height, column))
+        }
+    }
+
+    fn erase_line(&mut self) -> Result<()> {
+        // Text in the lower window has already been written out, so only the upper window can
+        // be erased.
+        if self.active_window == 1 {
+            let (line, column) = self.cursor;
+            if let Some(cells) = self.upper_window.get_mut(line) {
+                cells.iter_mut().skip(column).for_each(|cell| *cell = ' ');
+                self.upper_window_changed = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn buffer_mode(&mut self, _enable: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn show_status(&mut self, location: &str, progress: &str) -> Result<()> {
+        let line = format_status_line(location, progress, self.width as usize);
+        match self.upper_window_mode {
+            UpperWindowMode::Hide => {}
+            UpperWindowMode::Inline => {
+                self.print_lower(&format!("{}\n", line.trim_end()))?;
+            }
+            UpperWindowMode::Grid => {
+                if self.status_line.as_ref() != Some(&line) {
+                    self.status_line = Some(line);
+                    self.upper_window_changed = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn quit(&mut self) {
+        let _ = self.stdout.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interface() -> DumbInterface {
+        DumbInterface::new(80, 24, UpperWindowMode::Hide)
+    }
+
+    #[test]
+    fn lines_are_read_without_their_endings() {
+        let mut input = "look\r\nn\n".as_bytes();
+        let mut interface = interface();
+        assert_eq!(interface.read_input_from(&mut input).unwrap(), "look");
+        assert_eq!(interface.read_input_from(&mut input).unwrap(), "n");
+    }
+
+    #[test]
+    fn end_of_input_quits() {
+        let mut input = "look".as_bytes();
+        let mut interface = interface();
+        assert_eq!(interface.read_input_from(&mut input).unwrap(), "look");
+        let error = interface.read_input_from(&mut input).unwrap_err();
+        assert!(error.is_end_of_input());
+    }
+}
@@ -0,0 +1,155 @@
+//! The fonts a game can select with `set_font`, and the shapes of font 3 (character graphics).
+
+/// A font, numbered as for `set_font`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Font {
+    #[default]
+    Normal,
+    /// Font 2, which was never properly defined. No interpreter supports it.
+    Picture,
+    /// Font 3, used to draw boxes, maps and Beyond Zork's runes.
+    CharacterGraphics,
+    FixedPitch,
+}
+
+impl Font {
+    /// The font with the given number, if there is one.
+    pub fn from_number(number: u16) -> Option<Font> {
+        Some(match number {
+            1 => Font::Normal,
+            2 => Font::Picture,
+            3 => Font::CharacterGraphics,
+            4 => Font::FixedPitch,
+            _ => return None,
+        })
+    }
+
+    /// Whether the interface can show text in this font.
+    pub fn available(&self) -> bool {
+        *self != Font::Picture
+    }
+
+    pub fn number(&self) -> u16 {
+        match self {
+            Font::Normal => 1,
+            Font::Picture => 2,
+            Font::CharacterGraphics => 3,
+            Font::FixedPitch => 4,
+        }
+    }
+}
+
+/// The Unicode character closest to a character in font 3. The shapes are described by the
+/// picture of the font in the standard; lines at the edges of a cell are drawn through its
+/// middle, so that they join up with the corners.
+pub fn character_graphic(c: char) -> char {
+    match c {
+        '!' => '←',
+        '"' => '→',
+        '#' => '╱',
+        '$' => '╲',
+        '%' => ' ',
+        '&' | '\'' => '─',
+        '(' | ')' => '│',
+        '*' => '┴',
+        '+' => '┬',
+        ',' => '├',
+        '-' => '┤',
+        '.' => '└',
+        '/' => '┌',
+        '0' => '┐',
+        '1' => '┘',
+        '2' => '╰',
+        '3' => '╭',
+        '4' => '╮',
+        '5' => '╯',
+        '6' => '█',
+        '7' | '<' => '▀',
+        '8' | ';' => '▄',
+        '9' | '=' => '▌',
+        ':' | '>' => '▐',
+        '?' | 'C' => '▗',
+        '@' | 'D' => '▖',
+        'A' | 'E' => '▝',
+        'B' | 'F' => '▘',
+        'G' => '▚',
+        'H' => '▞',
+        'I' => '▙',
+        'J' => '▟',
+        'K' => '▔',
+        'L' => '▁',
+        'M' => '▏',
+        'N' => '▕',
+        // A bar that fills from the left, in eighths.
+        'O' => ' ',
+        'P' => '▏',
+        'Q' => '▎',
+        'R' => '▍',
+        'S' => '▌',
+        'T' => '▋',
+        'U' => '▊',
+        'V' => '▉',
+        'W' => '█',
+        'X' => '▕',
+        'Y' => '▏',
+        'Z' => '╳',
+        '[' => '┼',
+        '\\' => '↑',
+        ']' => '↓',
+        '^' => '↕',
+        '_' => '□',
+        // Beyond Zork's runes.
+        'a' => 'ᚪ',
+        'b' => 'ᛒ',
+        'c' => 'ᛇ',
+        'd' => 'ᛞ',
+        'e' => 'ᛖ',
+        'f' => 'ᚠ',
+        'g' => 'ᚷ',
+        'h' => 'ᚻ',
+        'i' => 'ᛁ',
+        'j' => 'ᛄ',
+        'k' => 'ᛦ',
+        'l' => 'ᛚ',
+        'm' => 'ᛗ',
+        'n' => 'ᚾ',
+        'o' => 'ᚩ',
+        'p' => 'ᛈ',
+        'q' => 'ᛩ',
+        'r' => 'ᚱ',
+        's' => 'ᛋ',
+        't' => 'ᛏ',
+        'u' => 'ᚢ',
+        'v' => 'ᚡ',
+        'w' => 'ᚹ',
+        'x' => 'ᛉ',
+        'y' => 'ᚣ',
+        'z' => 'ᛣ',
+        other => other,
+    }
+}
+
+/// An ASCII stand-in for a character from `character_graphic`, for terminals that can't show
+/// it.
+pub fn character_graphic_ascii(c: char) -> char {
+    match c {
+        '←' => '<',
+        '→' => '>',
+        '↑' => '^',
+        '↓' => 'v',
+        '↕' => '|',
+        '╱' => '/',
+        '╲' => '\\',
+        '╳' => 'X',
+        '─' | '▔' | '▁' => '-',
+        '│' | '▏' | '▕' => '|',
+        '┴' | '┬' | '├' | '┤' | '└' | '┌' | '┐' | '┘' | '┼' => '+',
+        '╰' | '╯' => '\'',
+        '╭' | '╮' => '.',
+        '□' => 'o',
+        ' ' => ' ',
+        'ᚠ'..='ᛰ' => '?',
+        // Every remaining shape is a block of some kind.
+        _ => '#',
+    }
+}
@@ -2,14 +2,17 @@ pub mod window;
 
 use std::{
     error::Error,
-    io,
+    io::{self, prelude::*},
     time::{Duration, Instant},
 };
 
 use crossterm::{
     self,
-    cursor::{position as cursor_pos, MoveLeft, MoveTo},
-    event::{self, read, Event, KeyCode, KeyEvent},
+    cursor::{position as cursor_pos, MoveLeft, MoveTo, RestorePosition, SavePosition},
+    event::{
+        self, read, Event, KeyCode, KeyEvent, KeyEventState, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
     execute, queue,
     style::{Attribute, Print, SetAttribute},
     terminal::{
@@ -20,13 +23,35 @@ use crossterm::{
 use tracing::warn;
 
 use crate::game::Result;
-use crate::interface::{ClearMode, InputCode, Interface};
-use window::{Constraint, Direction, TextStream, WindowKind, WindowManager};
+use crate::interface::{
+    format_status_line, unicode, CharSupport, ClearMode, Colour, InputCode, Interface, LineInput,
+};
+use window::{Constraint, Direction, TextGrid, TextStream, WindowKind, WindowManager};
+
+/// The longest time between two clicks that make a double click.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+
+/// The bit for a mouse button in the Z-machine's button state, numbering the buttons from the
+/// left.
+fn button_bit(button: MouseButton) -> u16 {
+    match button {
+        MouseButton::Left => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Right => 4,
+    }
+}
 
 pub struct TerminalInterface {
     wm: WindowManager,
     upper_screen_id: usize,
     lower_screen_id: usize,
+    /// The status line, in versions 1 to 3.
+    status_screen_id: Option<usize>,
+    version: u8,
+    /// When the mouse was last clicked, if that click could start a double click.
+    last_click: Option<Instant>,
+    mouse_position: (u16, u16),
+    mouse_buttons: u16,
 }
 
 impl TerminalInterface {
@@ -35,10 +60,122 @@ impl TerminalInterface {
             wm: WindowManager::new(),
             upper_screen_id: 0,
             lower_screen_id: 0,
+            status_screen_id: None,
+            version: 0,
+            last_click: None,
+            mouse_position: (1, 1),
+            mouse_buttons: 0,
         })
     }
 }
 
+impl TerminalInterface {
+    /// Work out which key an event is, if it's one the game can read. A second click in the
+    /// same place soon after the first is a double click.
+    fn input_code(&mut self, event: Event) -> Option<InputCode> {
+        if let Event::Mouse(MouseEvent { kind, .. }) = event {
+            match kind {
+                MouseEventKind::Down(button) => self.mouse_buttons |= button_bit(button),
+                MouseEventKind::Up(button) => self.mouse_buttons &= !button_bit(button),
+                _ => {}
+            }
+        }
+        let input = match event {
+            Event::Key(KeyEvent { code, state, .. }) => match code {
+                KeyCode::Enter => InputCode::Newline,
+                // The keypad can only be told apart on terminals that report it.
+                KeyCode::Char(c @ '0'..='9') if state.contains(KeyEventState::KEYPAD) => {
+                    InputCode::Keypad(c as u8 - b'0')
+                }
+                KeyCode::Char(c) => InputCode::Character(c),
+                KeyCode::Up => InputCode::CursorUp,
+                KeyCode::Down => InputCode::CursorDown,
+                KeyCode::Left => InputCode::CursorLeft,
+                KeyCode::Right => InputCode::CursorRight,
+                KeyCode::Backspace | KeyCode::Delete => InputCode::Delete,
+                KeyCode::Esc => InputCode::Escape,
+                KeyCode::F(n @ 1..=12) => InputCode::FunctionKey(n),
+                _ => return None,
+            },
+            Event::Mouse(MouseEvent {
+                kind: MouseEventKind::Down(MouseButton::Left),
+                column,
+                row,
+                ..
+            }) => {
+                let position = (column + 1, row + 1);
+                let now = Instant::now();
+                let double = self.last_click.is_some_and(|last| {
+                    self.mouse_position == position && now - last < DOUBLE_CLICK_TIME
+                });
+                self.mouse_position = position;
+                if double {
+                    self.last_click = None;
+                    InputCode::DoubleClick
+                } else {
+                    self.last_click = Some(now);
+                    InputCode::SingleClick
+                }
+            }
+            _ => return None,
+        };
+        Some(input)
+    }
+
+    /// Wait for the next event, returning it if it's a key the game can read.
+    fn next_key(&mut self) -> Result<Option<InputCode>> {
+        let input = self.input_code(event::read()?);
+        if let Some(InputCode::Character(c)) = input {
+            self.wm.print_char(c, true)?;
+        }
+        Ok(input)
+    }
+
+    /// Let the player edit a line of input, starting from the given text. The line ends with
+    /// Enter or one of the terminating keys. If there's a deadline and it passes, the text typed
+    /// so far is returned.
+    fn edit_line(
+        &mut self,
+        max_chars: usize,
+        mut line: String,
+        deadline: Option<Instant>,
+        terminators: &[InputCode],
+    ) -> Result<LineInput> {
+        self.wm.flush_buffer();
+        self.wm.set_active(self.lower_screen_id)?;
+        loop {
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline || !event::poll(deadline - now)? {
+                    return Ok(LineInput::Timeout(line));
+                }
+            }
+            match self.input_code(event::read()?) {
+                Some(InputCode::Newline) => {
+                    self.wm.print_char('\n', true)?;
+                    return Ok(LineInput::Done(line, InputCode::Newline));
+                }
+                Some(InputCode::Character(c)) => {
+                    if line.chars().count() < max_chars {
+                        self.wm.print_char(c, true)?;
+                        line.push(c);
+                    }
+                }
+                Some(InputCode::Delete) => {
+                    if !line.is_empty() {
+                        self.wm.backspace();
+                        line.pop();
+                    }
+                }
+                Some(input) if terminators.contains(&input) => {
+                    return Ok(LineInput::Done(line, input));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
 impl Interface for TerminalInterface {
     fn init(&mut self) -> Result<()> {
         self.wm.init()?;
@@ -48,11 +185,21 @@ impl Interface for TerminalInterface {
             Constraint::RightFixed(0),
             WindowKind::TextStream(TextStream::default()),
         )?;
+        if self.version <= 3 {
+            // The status line sits above both windows.
+            self.status_screen_id = Some(self.wm.split(
+                self.lower_screen_id,
+                Direction::Above,
+                Constraint::RightFixed(1),
+                WindowKind::TextGrid(TextGrid::default()),
+            )?);
+        }
+        // The upper window has no lines until the game splits the screen.
         self.upper_screen_id = self.wm.split(
             self.lower_screen_id,
             Direction::Above,
-            Constraint::RightFixed(5),
-            WindowKind::TextStream(TextStream::default()),
+            Constraint::RightFixed(0),
+            WindowKind::TextGrid(TextGrid::default()),
         )?;
         Ok(())
     }
@@ -70,7 +217,20 @@ impl Interface for TerminalInterface {
 
     /// Clear the entire window
     fn clear(&mut self, mode: ClearMode) -> Result<()> {
-        // todo!();
+        match mode {
+            ClearMode::FullUnsplit => {
+                self.wm.resize(self.upper_screen_id, 0)?;
+                self.wm.set_active(self.lower_screen_id)?;
+                self.wm.clear(self.upper_screen_id)?;
+                self.wm.clear(self.lower_screen_id)?;
+            }
+            ClearMode::Full => {
+                self.wm.clear(self.upper_screen_id)?;
+                self.wm.clear(self.lower_screen_id)?;
+            }
+            ClearMode::Single(1) => self.wm.clear(self.upper_screen_id)?,
+            ClearMode::Single(_) => self.wm.clear(self.lower_screen_id)?,
+        }
         Ok(())
     }
 
@@ -110,73 +270,89 @@ impl Interface for TerminalInterface {
         Ok(())
     }
 
+    fn set_colour(&mut self, foreground: Option<Colour>, background: Option<Colour>) -> Result<()> {
+        self.wm.set_colour(foreground, background);
+        Ok(())
+    }
+
+    fn char_support(&self, c: char) -> CharSupport {
+        unicode::char_support(c)
+    }
+
     fn set_z_machine_version(&mut self, version: u8) {
-        // todo!();
+        self.version = version;
     }
 
     fn read_char(&mut self) -> Result<InputCode> {
         self.wm.flush_buffer();
         self.wm.set_active(self.lower_screen_id)?;
         loop {
-            match event::read()? {
-                Event::Key(KeyEvent { code, .. }) => match code {
-                    KeyCode::Enter => return Ok(InputCode::Newline),
-                    KeyCode::Char(c) => {
-                        self.wm.print_char(c, true)?;
-                        return Ok(InputCode::Character(c));
-                    }
-                    KeyCode::Up => return Ok(InputCode::CursorUp),
-                    KeyCode::Down => return Ok(InputCode::CursorDown),
-                    KeyCode::Left => return Ok(InputCode::CursorLeft),
-                    KeyCode::Right => return Ok(InputCode::CursorRight),
-                    KeyCode::Backspace | KeyCode::Delete => return Ok(InputCode::Delete),
-                    KeyCode::Esc => return Ok(InputCode::Escape),
-                    _ => {}
-                },
-                _ => {}
+            if let Some(input) = self.next_key()? {
+                return Ok(input);
             }
         }
     }
 
-    fn read_line(&mut self, max_chars: usize) -> Result<String> {
+    fn read_line(
+        &mut self,
+        max_chars: usize,
+        terminators: &[InputCode],
+    ) -> Result<(String, InputCode)> {
+        match self.edit_line(max_chars, String::new(), None, terminators)? {
+            LineInput::Done(line, terminator) => Ok((line, terminator)),
+            // There's no deadline.
+            LineInput::Timeout(line) => Ok((line, InputCode::Newline)),
+        }
+    }
+
+    fn read_line_timed(
+        &mut self,
+        max_chars: usize,
+        initial: &str,
+        timeout: Duration,
+        terminators: &[InputCode],
+    ) -> Result<LineInput> {
+        self.edit_line(
+            max_chars,
+            initial.to_string(),
+            Some(Instant::now() + timeout),
+            terminators,
+        )
+    }
+
+    fn read_char_timed(&mut self, timeout: Duration) -> Result<Option<InputCode>> {
         self.wm.flush_buffer();
         self.wm.set_active(self.lower_screen_id)?;
-        let mut line = String::new();
+        let deadline = Instant::now() + timeout;
         loop {
-            match event::read()? {
-                Event::Resize(..) => {
-                    // Todo
-                }
-                Event::Key(KeyEvent { code, .. }) => match code {
-                    KeyCode::Enter => {
-                        self.wm.print_char('\n', true)?;
-                        break;
-                    }
-                    KeyCode::Esc => {
-                        panic!("Yes");
-                    }
-                    KeyCode::Char(c) => {
-                        if line.len() < max_chars {
-                            self.wm.print_char(c, true)?;
-                            line.push(c);
-                        }
-                    }
-                    KeyCode::Backspace => {
-                        if !line.is_empty() {
-                            self.wm.backspace();
-                            line.pop();
-                        }
-                    }
-                    _ => {}
-                },
-                _ => {}
+            let now = Instant::now();
+            if now >= deadline || !event::poll(deadline - now)? {
+                return Ok(None);
+            }
+            if let Some(input) = self.next_key()? {
+                return Ok(Some(input));
             }
         }
-        Ok(line)
+    }
+
+    fn mouse_available(&self) -> bool {
+        true
+    }
+
+    fn mouse_position(&self) -> (u16, u16) {
+        self.mouse_position
+    }
+
+    fn mouse_buttons(&self) -> u16 {
+        self.mouse_buttons
     }
 
     fn split_screen(&mut self, split: u16) -> Result<()> {
-        // todo!();
+        self.wm.resize(self.upper_screen_id, split)?;
+        if self.version == 3 {
+            // In version 3, splitting the screen clears the upper window.
+            self.wm.clear(self.upper_screen_id)?;
+        }
         Ok(())
     }
 
@@ -190,12 +366,23 @@ impl Interface for TerminalInterface {
             0 => self.lower_screen_id,
             _ => todo!(),
         });
+        if active == 1 {
+            // Selecting the upper window puts its cursor in the top left corner.
+            self.wm.set_cursor(1, 1)?;
+        }
         Ok(())
     }
 
     fn set_cursor(&mut self, line: u16, column: u16) -> Result<()> {
-        // todo!();
-        Ok(())
+        self.wm.set_cursor(line, column)
+    }
+
+    fn get_cursor(&mut self) -> Result<(u16, u16)> {
+        Ok(self.wm.cursor())
+    }
+
+    fn erase_line(&mut self) -> Result<()> {
+        self.wm.erase_line()
     }
 
     fn buffer_mode(&mut self, enable: bool) -> Result<()> {
@@ -203,6 +390,21 @@ impl Interface for TerminalInterface {
         Ok(())
     }
 
+    fn show_status(&mut self, location: &str, progress: &str) -> Result<()> {
+        let status = match self.status_screen_id {
+            Some(status) => status,
+            None => return Ok(()),
+        };
+        let (width, _) = self.wm.size();
+        let line = format_status_line(location, progress, width as usize);
+        let active = self.wm.active();
+        self.wm.set_active(status)?;
+        self.wm.set_cursor(1, 1)?;
+        self.wm.set_reverse(true);
+        self.wm.print(&line, true)?;
+        self.wm.set_active(active)
+    }
+
     /// Close the UI immediately.
     fn quit(&mut self) {
         todo!();
@@ -5,8 +5,14 @@ use std::mem;
 use crossterm::{
     self,
     cursor::MoveTo,
+    event::{
+        DisableMouseCapture, EnableMouseCapture, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
     execute, queue,
-    style::{Attribute, SetAttribute},
+    style::{
+        Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+    },
     terminal::{
         disable_raw_mode, enable_raw_mode, size as term_size, Clear, ClearType,
         EnterAlternateScreen, LeaveAlternateScreen,
@@ -16,6 +22,7 @@ use tracing::warn;
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use crate::game::Result;
+use crate::interface::Colour;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Direction {
@@ -30,6 +37,47 @@ pub struct Style {
     bold: bool,
     italic: bool,
     reverse: bool,
+    foreground: Colour,
+    background: Colour,
+}
+
+impl Style {
+    /// Set the terminal's colours, and reverse video, for text in this style.
+    fn apply_colours(&self, stdout: &mut io::Stdout) -> Result<()> {
+        queue!(
+            stdout,
+            SetForegroundColor(terminal_colour(self.foreground)),
+            SetBackgroundColor(terminal_colour(self.background)),
+            SetAttribute(if self.reverse {
+                Attribute::Reverse
+            } else {
+                Attribute::NoReverse
+            })
+        )?;
+        Ok(())
+    }
+}
+
+/// Map a colour onto the terminal. Palette colours use the terminal's own named colours, so they
+/// match its theme; true colours are sent as RGB.
+fn terminal_colour(colour: Colour) -> Color {
+    match colour {
+        Colour::Default => Color::Reset,
+        Colour::Black => Color::Black,
+        Colour::Red => Color::DarkRed,
+        Colour::Green => Color::DarkGreen,
+        Colour::Yellow => Color::DarkYellow,
+        Colour::Blue => Color::DarkBlue,
+        Colour::Magenta => Color::DarkMagenta,
+        Colour::Cyan => Color::DarkCyan,
+        Colour::White => Color::White,
+        Colour::LightGrey => Color::Grey,
+        Colour::DarkGrey => Color::DarkGrey,
+        Colour::MediumGrey | Colour::True(_) => {
+            let (r, g, b) = colour.rgb().unwrap();
+            Color::Rgb { r, g, b }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -46,9 +94,15 @@ pub struct Rectangle {
 }
 
 impl Rectangle {
+    /// Split off a part of the given size (or all of it, if it's smaller) in one direction,
+    /// returning that part and what's left.
     pub fn split_fixed(&self, direction: Direction, size: u16) -> (Self, Self) {
         let mut sized = self.clone();
         let mut unconstrained = self.clone();
+        let size = match direction {
+            Direction::Above | Direction::Below => size.min(self.height),
+            Direction::Left | Direction::Right => size.min(self.width),
+        };
 
         match direction {
             Direction::Above => {
@@ -69,7 +123,7 @@ impl Rectangle {
             Direction::Right => {
                 sized.width = size;
                 unconstrained.width -= size;
-                sized.y += unconstrained.width;
+                sized.x += unconstrained.width;
             }
         };
         (sized, unconstrained)
@@ -105,6 +159,12 @@ struct ScreenModel {
 }
 
 impl ScreenModel {
+    /// Move the cursor, keeping it inside the area.
+    fn cursor_to(&mut self, x: u16, y: u16) {
+        self.cursor.x = x.min(self.area.width.saturating_sub(1));
+        self.cursor.y = y.min(self.area.height.saturating_sub(1));
+    }
+
     fn sync_cursor(&self) -> Result<()> {
         let mut stdout = io::stdout();
         execute!(
@@ -117,21 +177,56 @@ impl ScreenModel {
 
 impl Window {
     fn redraw(&mut self) -> Result<()> {
-        self.screen_model.cursor.x = 0;
-        self.screen_model.cursor.y = 0;
-
-        self.screen_model.sync_cursor()?;
         match &mut self.kind {
             WindowKind::TextStream(stream) => {
+                self.screen_model.cursor.x = 0;
+                self.screen_model.cursor.y = 0;
+                self.screen_model.sync_cursor()?;
                 stream.redraw(&mut self.screen_model)?;
             }
-            _ => {
-                todo!();
+            WindowKind::TextGrid(grid) => {
+                grid.redraw(&self.screen_model, self.active_style)?;
             }
         }
         Ok(())
     }
 
+    /// Forget everything in the window, and blank it in its current background colour.
+    fn clear(&mut self) -> Result<()> {
+        match &mut self.kind {
+            WindowKind::TextStream(stream) => {
+                stream.lines.clear();
+                stream.buffer.clear();
+                // A text stream only draws its lines, so blank the rest of the window too.
+                let ScreenModel { area, .. } = self.screen_model;
+                let mut stdout = io::stdout();
+                self.active_style.apply_colours(&mut stdout)?;
+                for y in area.y..area.y + area.height {
+                    queue!(
+                        stdout,
+                        MoveTo(area.x, y),
+                        Print(" ".repeat(area.width as usize))
+                    )?;
+                }
+            }
+            WindowKind::TextGrid(grid) => {
+                grid.rows.clear();
+                self.screen_model.cursor_to(0, 0);
+            }
+        }
+        self.redraw()
+    }
+
+    /// Move the cursor of a text grid. The cursor of a text stream always follows its text, so
+    /// it can't be moved.
+    fn cursor_to(&mut self, x: u16, y: u16) -> Result<()> {
+        if let WindowKind::TextGrid(_) = self.kind {
+            self.screen_model.cursor_to(x, y);
+            self.screen_model.sync_cursor()?;
+        }
+        Ok(())
+    }
+
     fn print(&mut self, text: &str, immediate: bool) -> Result<()> {
         match &mut self.kind {
             WindowKind::TextStream(stream) => {
@@ -152,8 +247,8 @@ impl Window {
                     stream.flush_buffer(&mut self.screen_model)?;
                 }
             }
-            _ => {
-                todo!();
+            WindowKind::TextGrid(grid) => {
+                grid.print(text, self.active_style, &mut self.screen_model)?;
             }
         }
         Ok(())
@@ -179,8 +274,9 @@ impl Window {
                     stream.flush_buffer(&mut self.screen_model)?;
                 }
             }
-            _ => {
-                todo!();
+            WindowKind::TextGrid(grid) => {
+                let text = text.encode_utf8(&mut [0; 4]).to_owned();
+                grid.print(&text, self.active_style, &mut self.screen_model)?;
             }
         }
         Ok(())
@@ -223,6 +319,29 @@ impl Window {
         }
         Ok(())
     }
+
+    fn erase_line(&mut self) -> Result<()> {
+        match &mut self.kind {
+            WindowKind::TextStream(stream) => {
+                // The cursor of a text stream is always at the end of its text, so there is
+                // nothing after it to forget; only the screen needs clearing.
+                stream.flush_buffer(&mut self.screen_model)?;
+            }
+            WindowKind::TextGrid(grid) => {
+                grid.erase_line(self.active_style, &self.screen_model);
+            }
+        }
+        let ScreenModel { area, cursor } = self.screen_model;
+        let mut stdout = io::stdout();
+        self.screen_model.sync_cursor()?;
+        self.active_style.apply_colours(&mut stdout)?;
+        queue!(
+            stdout,
+            Print(" ".repeat(area.width.saturating_sub(cursor.x) as usize))
+        )?;
+        stdout.flush()?;
+        self.screen_model.sync_cursor()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -284,6 +403,7 @@ impl TextStream {
                 }
                 first = false;
                 for chunk in &line[chunk_from..] {
+                    chunk.style.apply_colours(&mut stdout)?;
                     stdout.write(chunk.value.as_bytes());
                 }
                 chunk_from = 0;
@@ -319,6 +439,7 @@ impl TextStream {
             first = false;
             for chunk in line {
                 line_consumed += chunk.value.width();
+                chunk.style.apply_colours(&mut stdout)?;
                 stdout.write(chunk.value.as_bytes())?;
             }
             for c in 0..(screen_model.area.width as usize - line_consumed) {
@@ -332,31 +453,84 @@ impl TextStream {
     }
 }
 
-#[derive(Debug, Clone)]
+/// A grid of character cells, such as the upper window. Text is drawn as soon as it's printed,
+/// starting at the cursor, and never wraps or scrolls: anything past the right edge is lost.
+#[derive(Debug, Default, Clone)]
 pub struct TextGrid {
-    lines: Vec<Vec<Chunk>>,
-    screen_model: ScreenModel,
+    /// The cells of each row, top first. Cells past the end of a row are blank.
+    rows: Vec<Vec<Cell>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    value: char,
+    style: Style,
+}
+
+impl Cell {
+    fn blank(style: Style) -> Cell {
+        Cell { value: ' ', style }
+    }
 }
 
 impl TextGrid {
-    fn new(screen_model: ScreenModel) -> Self {
-        Self {
-            screen_model,
-            lines: Vec::with_capacity(screen_model.area.height as usize),
+    fn print(&mut self, text: &str, style: Style, screen_model: &mut ScreenModel) -> Result<()> {
+        let mut stdout = io::stdout();
+        let ScreenModel { area, .. } = *screen_model;
+        screen_model.sync_cursor()?;
+        style.apply_colours(&mut stdout)?;
+        for value in text.chars() {
+            let cursor = &mut screen_model.cursor;
+            if value == '\n' {
+                cursor.x = 0;
+                cursor.y = (cursor.y + 1).min(area.height.saturating_sub(1));
+                queue!(stdout, MoveTo(area.x, area.y + cursor.y))?;
+                continue;
+            }
+            if cursor.x >= area.width || cursor.y >= area.height {
+                continue;
+            }
+            let (x, y) = (cursor.x as usize, cursor.y as usize);
+            if self.rows.len() <= y {
+                self.rows.resize(y + 1, Vec::new());
+            }
+            let row = &mut self.rows[y];
+            if row.len() <= x {
+                row.resize(x + 1, Cell::blank(style));
+            }
+            row[x] = Cell { value, style };
+            queue!(stdout, Print(value))?;
+            cursor.x += 1;
         }
+        stdout.flush()?;
+        Ok(())
     }
 
-    fn cursor_to(&mut self, x: u16, y: u16) {
-        if x >= self.screen_model.area.width {
-            self.screen_model.cursor.x = self.screen_model.area.width.saturating_sub(1);
-        } else {
-            self.screen_model.cursor.x = x;
+    /// Blank the cells from the cursor to the end of its row.
+    fn erase_line(&mut self, style: Style, screen_model: &ScreenModel) {
+        let ScreenModel { area, cursor } = *screen_model;
+        if let Some(row) = self.rows.get_mut(cursor.y as usize) {
+            row.resize(area.width as usize, Cell::blank(style));
+            let from = (cursor.x as usize).min(row.len());
+            row[from..].fill(Cell::blank(style));
         }
-        if y >= self.screen_model.area.height {
-            self.screen_model.cursor.y  = self.screen_model.area.height.saturating_sub(1);
-        } else {
-            self.screen_model.cursor.y = y;
+    }
+
+    /// Draw every cell. Blank cells past the end of their row are drawn in the given style.
+    fn redraw(&self, screen_model: &ScreenModel, blank: Style) -> Result<()> {
+        let mut stdout = io::stdout();
+        let ScreenModel { area, .. } = *screen_model;
+        for y in 0..area.height {
+            queue!(stdout, MoveTo(area.x, area.y + y))?;
+            let row = self.rows.get(y as usize).map_or(&[][..], |row| &row[..]);
+            for x in 0..area.width as usize {
+                let cell = row.get(x).copied().unwrap_or(Cell::blank(blank));
+                cell.style.apply_colours(&mut stdout)?;
+                queue!(stdout, Print(cell.value))?;
+            }
         }
+        stdout.flush()?;
+        screen_model.sync_cursor()
     }
 }
 
@@ -427,6 +601,9 @@ pub struct WindowManager {
     active_window: usize,
     root_window: usize,
     active: bool,
+    /// Whether the terminal was asked to report keys unambiguously, which tells the keypad
+    /// apart.
+    keyboard_enhanced: bool,
 }
 
 impl WindowManager {
@@ -438,7 +615,19 @@ impl WindowManager {
         self.active = true;
         let mut stdout = io::stdout();
         enable_raw_mode()?;
-        execute!(stdout, EnterAlternateScreen, Clear(ClearType::All))?;
+        execute!(
+            stdout,
+            EnterAlternateScreen,
+            Clear(ClearType::All),
+            EnableMouseCapture
+        )?;
+        // Terminals that don't know this request ignore it, but it can't be sent at all on
+        // Windows.
+        self.keyboard_enhanced = execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+        )
+        .is_ok();
         Ok(())
     }
 
@@ -447,8 +636,17 @@ impl WindowManager {
             return Ok(());
         }
         let mut stdout = io::stdout();
+        if self.keyboard_enhanced {
+            execute!(stdout, PopKeyboardEnhancementFlags)?;
+        }
         disable_raw_mode()?;
-        execute!(stdout, Clear(ClearType::All), LeaveAlternateScreen)?;
+        execute!(
+            stdout,
+            DisableMouseCapture,
+            ResetColor,
+            Clear(ClearType::All),
+            LeaveAlternateScreen
+        )?;
         Ok(())
     }
 
@@ -512,6 +710,20 @@ impl WindowManager {
 
         let split_node_id = self.insert_node(split_node);
 
+        // The pair takes the window's place in its parent.
+        if let Some(Some(WindowNode::PairWindow {
+            child_left,
+            child_right,
+            ..
+        })) = parent.map(|parent| &mut self.items[parent])
+        {
+            if *child_left == node {
+                *child_left = split_node_id;
+            } else if *child_right == node {
+                *child_right = split_node_id;
+            }
+        }
+
         self.items[node]
             .as_mut()
             .unwrap()
@@ -585,6 +797,92 @@ impl WindowManager {
         Ok(())
     }
 
+    /// Change the colours used for text printed to the active window from now on.
+    pub fn set_colour(&mut self, foreground: Option<Colour>, background: Option<Colour>) {
+        match &mut self.items[self.active_window] {
+            Some(WindowNode::Window { window, .. }) => {
+                if let Some(foreground) = foreground {
+                    window.active_style.foreground = foreground;
+                }
+                if let Some(background) = background {
+                    window.active_style.background = background;
+                }
+            }
+            _ => panic!(),
+        }
+    }
+
+    /// Turn reverse video on or off for text printed to the active window from now on.
+    pub fn set_reverse(&mut self, reverse: bool) {
+        match &mut self.items[self.active_window] {
+            Some(WindowNode::Window { window, .. }) => window.active_style.reverse = reverse,
+            _ => panic!(),
+        }
+    }
+
+    /// The id of the active window.
+    pub fn active(&self) -> usize {
+        self.active_window
+    }
+
+    /// Returns the cursor position in the active window, as a 1-indexed (line, column).
+    pub fn cursor(&self) -> (u16, u16) {
+        match &self.items[self.active_window] {
+            Some(WindowNode::Window { window, .. }) => {
+                let cursor = window.screen_model.cursor;
+                (cursor.y + 1, cursor.x + 1)
+            }
+            _ => panic!(),
+        }
+    }
+
+    /// Move the cursor in the active window to a 1-indexed (line, column), if it's a text grid.
+    pub fn set_cursor(&mut self, line: u16, column: u16) -> Result<()> {
+        match &mut self.items[self.active_window] {
+            Some(WindowNode::Window { window, .. }) => {
+                window.cursor_to(column.saturating_sub(1), line.saturating_sub(1))
+            }
+            _ => panic!(),
+        }
+    }
+
+    /// Clear a window.
+    pub fn clear(&mut self, id: usize) -> Result<()> {
+        match &mut self.items[id] {
+            Some(WindowNode::Window { window, .. }) => window.clear(),
+            _ => panic!(),
+        }
+    }
+
+    /// Change the size of a window that was split off another, in lines (or columns, if it
+    /// was split off to the left or right). The other window takes the rest of the space.
+    pub fn resize(&mut self, id: usize, size: u16) -> Result<()> {
+        let parent = match &self.items[id] {
+            Some(WindowNode::Window { parent, .. }) => *parent,
+            _ => panic!(),
+        };
+        if let Some(Some(WindowNode::PairWindow {
+            child_right,
+            constraint,
+            ..
+        })) = parent.map(|parent| &mut self.items[parent])
+        {
+            if *child_right == id {
+                *constraint = Constraint::RightFixed(size);
+            }
+        }
+        self.reflow()?;
+        self.set_active(self.active_window)
+    }
+
+    /// Erase from the cursor to the end of the line in the active window.
+    pub fn erase_line(&mut self) -> Result<()> {
+        match &mut self.items[self.active_window] {
+            Some(WindowNode::Window { window, .. }) => window.erase_line(),
+            _ => panic!(),
+        }
+    }
+
     pub fn backspace(&mut self) -> Result<()> {
         match &mut self.items[self.active_window] {
             Some(WindowNode::Window { window, .. }) => {
@@ -601,6 +899,8 @@ impl WindowManager {
             return Ok(());
         }
         self.reflow_window(self.root_window, rect);
+        // Windows only draw what they hold, so start from a blank screen.
+        queue!(io::stdout(), ResetColor, Clear(ClearType::All))?;
         self.redraw_all()?;
         Ok(())
     }
@@ -624,6 +924,8 @@ impl WindowManager {
         match &mut self.items[id] {
             Some(WindowNode::Window { window, .. }) => {
                 window.screen_model.area = rect;
+                let cursor = window.screen_model.cursor;
+                window.screen_model.cursor_to(cursor.x, cursor.y);
             }
             Some(WindowNode::PairWindow {
                 area,
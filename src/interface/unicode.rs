@@ -0,0 +1,44 @@
+//! Detecting which characters the terminal can show and accept, for `check_unicode`.
+
+use std::env;
+use std::sync::OnceLock;
+
+use unicode_width::UnicodeWidthChar;
+
+/// Whether the interface can show and accept a character.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CharSupport {
+    /// The character can be printed.
+    pub output: bool,
+    /// The character can be typed by the player.
+    pub input: bool,
+}
+
+/// Whether the locale's character set is UTF-8. The first of `LC_ALL`, `LC_CTYPE` and `LANG`
+/// that is set decides, as it does for the C library.
+fn utf8_locale() -> bool {
+    static UTF8: OnceLock<bool> = OnceLock::new();
+    *UTF8.get_or_init(|| {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        locale.contains(".utf-8") || locale.contains(".utf8")
+    })
+}
+
+/// Work out whether a terminal can show and accept a character. Outside a UTF-8 locale only
+/// ASCII is safe. Inside one, anything with a display width can be shown and typed; control
+/// characters have no width and can be neither.
+pub fn char_support(c: char) -> CharSupport {
+    let supported = match c {
+        ' '..='~' => true,
+        _ => utf8_locale() && c.width().is_some(),
+    };
+    CharSupport {
+        output: supported,
+        input: supported,
+    }
+}
@@ -5,21 +5,78 @@ pub mod interface;
 pub mod loader;
 
 use std::fs;
+use std::path::Path;
+use std::rc::Rc;
 
-use crate::cli::{Cli, InterfaceMode};
-use crate::game::Result;
+use tracing::warn;
+
+use crate::cli::{Cli, InterfaceMode, SoundMode};
+use crate::game::error::GameError;
+use crate::game::sound::{NullBackend, PlayerBackend};
+use crate::game::{Options, Result};
+use crate::loader::blorb::{ChunkKind, ExecutableSystem};
+use crate::loader::BlorbLoader;
 use game::state::GameState;
-use interface::{Interface, TerminalInterface};
+use interface::{DumbInterface, Interface, TerminalInterface};
 
 pub fn run(args: Cli) -> Result<()> {
-    let game_file = fs::read(&args.game_file)?;
+    let mut game_file = fs::read(&args.game_file)?;
+
+    // Pictures and sounds come from the story file itself if it's a Blorb file.
+    let mut resources = None;
+    if BlorbLoader::is_blorb(&game_file) {
+        let blorb = BlorbLoader::new(game_file)?;
+        game_file = match blorb.executable() {
+            Some(chunk)
+                if chunk.kind
+                    == (ChunkKind::Executable {
+                        system: ExecutableSystem::ZCode,
+                    }) =>
+            {
+                chunk.data.to_vec()
+            }
+            _ => return Err(GameError::invalid_file()),
+        };
+        resources = Some(Rc::new(blorb));
+    }
+    if let Some(blorb_file) = &args.blorb {
+        resources = Some(Rc::new(BlorbLoader::new(fs::read(blorb_file)?)?));
+    }
 
     let interface_type = args.interface.unwrap_or(InterfaceMode::Terminal);
     let mut interface: Box<dyn Interface> = match interface_type {
         InterfaceMode::Terminal => Box::new(TerminalInterface::new()?),
+        InterfaceMode::Dumb => Box::new(DumbInterface::new(
+            args.width,
+            args.height,
+            args.upper_window,
+        )),
+    };
+
+    let options = Options {
+        story_name: Path::new(&args.game_file)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| Options::default().story_name),
+        replay: args.replay,
+        record: args.record,
+        frames: args.frames,
+        auxiliary_directory: args.aux_dir,
+        undo_depth: args.undo_depth,
+        command_prefix: args.command_prefix,
     };
 
-    let mut game_state = GameState::new(game_file, interface.as_mut())?;
+    let mut game_state = GameState::new(game_file, interface.as_mut(), options, resources)?;
+    match args.sound {
+        SoundMode::Off => {}
+        SoundMode::Log => game_state.set_sound_backend(Box::<NullBackend>::default()),
+        // Without a player, the story is told there are no sounds rather than have them go
+        // unheard.
+        SoundMode::Player => match PlayerBackend::new(&args.sound_player) {
+            Ok(backend) => game_state.set_sound_backend(Box::new(backend)),
+            Err(e) => warn!("Sound is off: {}", e),
+        },
+    }
 
     let result = game_state.run();
 
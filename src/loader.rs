@@ -1,5 +1,4 @@
+pub mod blorb;
 pub mod iff;
 
-// mod blorb;
-
-// pub use blorb::BlorbLoader;
+pub use blorb::BlorbLoader;
@@ -1,9 +1,13 @@
 use std::collections::HashMap;
+use std::io::Cursor;
+use std::ops::Range;
 
-use crate::game::Result;
 use crate::game::error::GameError;
+use crate::game::Result;
+use crate::loader::iff::{self, IffReader};
 
-
+/// The usage of a resource, as given in the resource index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum IndexKind {
     Picture,
     Sound,
@@ -12,18 +16,19 @@ pub enum IndexKind {
 }
 
 impl IndexKind {
-    pub fn from_index_name(name: &str) -> Option<IndexKind> {
+    pub fn from_index_name(name: &[u8; 4]) -> Option<IndexKind> {
         match name {
-            "Pict" => Some(IndexKind::Picture),
-            "Snd " => Some(IndexKind::Sound),
-            "Exec" => Some(IndexKind::Executable),
-            "Data" => Some(IndexKind::Data),
+            b"Pict" => Some(IndexKind::Picture),
+            b"Snd " => Some(IndexKind::Sound),
+            b"Exec" => Some(IndexKind::Executable),
+            b"Data" => Some(IndexKind::Data),
             _ => None,
         }
     }
 }
 
-enum ExectuableSystem {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecutableSystem {
     ZCode,
     Glulx,
     TADS2,
@@ -36,36 +41,106 @@ enum ExectuableSystem {
     MagneticScrolls,
     AdvSys,
     Native,
-    Other(String),
+    Other([u8; 4]),
 }
 
-enum PictureFormat {
+impl ExecutableSystem {
+    fn from_chunk_id(id: &[u8; 4]) -> ExecutableSystem {
+        match id {
+            b"ZCOD" => ExecutableSystem::ZCode,
+            b"GLUL" => ExecutableSystem::Glulx,
+            b"TAD2" => ExecutableSystem::TADS2,
+            b"TAD3" => ExecutableSystem::TADS3,
+            b"HUGO" => ExecutableSystem::Hugo,
+            b"ALAN" => ExecutableSystem::Alan,
+            b"ADRI" => ExecutableSystem::Adrift,
+            b"LEVE" => ExecutableSystem::Level9,
+            b"AGT " => ExecutableSystem::AGT,
+            b"MAGS" => ExecutableSystem::MagneticScrolls,
+            b"ADVS" => ExecutableSystem::AdvSys,
+            b"EXEC" => ExecutableSystem::Native,
+            other => ExecutableSystem::Other(*other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PictureFormat {
     Png,
     Jpeg,
     Placeholder,
+    Other([u8; 4]),
 }
 
-enum SoundFormat {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SoundFormat {
     Ogg,
     Aiff,
     Mod,
     Song,
+    Other([u8; 4]),
 }
 
-enum ChunkKind {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkKind {
     Picture { format: PictureFormat },
     Sound { format: SoundFormat },
     Data,
     Executable { system: ExecutableSystem },
 }
 
+impl ChunkKind {
+    fn new(usage: IndexKind, id: &[u8; 4]) -> ChunkKind {
+        match usage {
+            IndexKind::Picture => ChunkKind::Picture {
+                format: match id {
+                    b"PNG " => PictureFormat::Png,
+                    b"JPEG" => PictureFormat::Jpeg,
+                    b"Rect" => PictureFormat::Placeholder,
+                    other => PictureFormat::Other(*other),
+                },
+            },
+            IndexKind::Sound => ChunkKind::Sound {
+                format: match id {
+                    b"OGGV" => SoundFormat::Ogg,
+                    b"AIFF" => SoundFormat::Aiff,
+                    b"MOD " => SoundFormat::Mod,
+                    b"SONG" => SoundFormat::Song,
+                    other => SoundFormat::Other(*other),
+                },
+            },
+            IndexKind::Data => ChunkKind::Data,
+            IndexKind::Executable => ChunkKind::Executable {
+                system: ExecutableSystem::from_chunk_id(id),
+            },
+        }
+    }
+}
+
+/// A resource stored in a Blorb file.
 pub struct Chunk<'a> {
-    data: &'a [u8],
+    pub number: u32,
+    pub kind: ChunkKind,
+    /// The content of the chunk. For resources stored as IFF forms (such as AIFF sounds),
+    /// this is the entire form, including its header.
+    pub data: &'a [u8],
+}
+
+struct IndexEntry {
+    usage: IndexKind,
+    number: u32,
     kind: ChunkKind,
+    range: Range<usize>,
 }
 
+/// A Blorb resource file.
 pub struct BlorbLoader {
     data: Vec<u8>,
+    index: Vec<IndexEntry>,
+    lookup: HashMap<(IndexKind, u32), usize>,
+    /// The number of times to play each sound listed in the `Loop` chunk, where 0 means
+    /// forever.
+    loops: HashMap<u32, u32>,
 }
 
 pub struct ChunkIter<'a> {
@@ -74,55 +149,283 @@ pub struct ChunkIter<'a> {
 }
 
 impl<'a> Iterator for ChunkIter<'a> {
-    type Item = Chunk;
+    type Item = Chunk<'a>;
+
     fn next(&mut self) -> Option<Self::Item> {
-        if from >= self.loader.data.len() {
-            return None
-        }
+        let entry = self.loader.index.get(self.from)?;
+        self.from += 1;
+        Some(self.loader.chunk(entry))
     }
 }
 
-fn invalid_file_error() -> GameError {
-    GameError::invalid_file().detail("Not a blorb file.")
-}
-
-trait IifHelpers {
-    /// Require th 
-    fn try_read(data: &[u8], range: I) -> Result<&<I as SliceIndex<[T]>>::Output> where I: SliceIndex<[T]>  {
+fn invalid_file_error(detail: &str) -> GameError {
+    let mut error = GameError::invalid_file();
+    error.detail(detail);
+    error
 }
 
-fn require_text(data: &[u8], text: &str) -> Result<()> {
-    if data[..text.len()] != text.as_bytes() {
-        Err(GameError::invalid_file().detail("Not a blorb file."))
-    } else {
-        Ok(())
+impl BlorbLoader {
+    /// Returns true if the data looks like a Blorb file.
+    pub fn is_blorb(data: &[u8]) -> bool {
+        data.len() >= 12 && &data[0..4] == b"FORM" && &data[8..12] == b"IFRS"
     }
-}
 
-fn try_read(data: &[u8], range: I) -> Result<&<I as SliceIndex<[T]>>::Output> where I: SliceIndex<[T]>  {
-    data.get(range).ok_or_else(invalid_file_error) 
-}
-
-impl BlorbLoader {
-    pub fn new<D: Into<Vec<u8>>(data: D) -> Result<BlorbFile> {
+    pub fn new<D: Into<Vec<u8>>>(data: D) -> Result<BlorbLoader> {
         let data: Vec<u8> = data.into();
-        require_text(try_read(&data, 0..4)?, &"FORM")?;
 
-        let data_len = u32::from_be_bytes(&[4..8]);
-        if data_len != data.len() - 8 {
-            return Err(invalid_file_error())
+        let form = match IffReader::new(Cursor::new(&data)).load() {
+            Ok(iff::Chunk::Form(form)) if form.kind() == b"IFRS" => form,
+            Ok(_) => return Err(invalid_file_error("Not a blorb file.")),
+            Err(e) => return Err(invalid_file_error(&e.to_string())),
+        };
+
+        // Resources are indexed by the offset of their chunk from the start of the file.
+        let mut chunks = HashMap::new();
+        let mut offset = 12;
+        for chunk in form.chunks() {
+            let content = offset + 8..offset + 8 + chunk.content_length() as usize;
+            let range = match chunk {
+                iff::Chunk::Data(_) => content,
+                _ => offset..content.end,
+            };
+            chunks.insert(offset, (*chunk.kind(), range));
+            offset += chunk.size() as usize;
         }
 
-        require_text(&data[8..12], &"IFRS")?;
+        let index_data = match form.chunks().first() {
+            Some(iff::Chunk::Data(chunk)) if chunk.kind() == b"RIdx" => chunk.data(),
+            _ => return Err(invalid_file_error("Missing resource index.")),
+        };
+
+        let index = Self::read_index(index_data, &chunks)?;
+        let lookup = index
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| ((entry.usage, entry.number), i))
+            .collect();
 
-        let index = Self::read_index(&[12..])?;
+        let loops = form
+            .chunks()
+            .iter()
+            .find_map(|chunk| match chunk {
+                iff::Chunk::Data(chunk) if chunk.kind() == b"Loop" => Some(chunk.data()),
+                _ => None,
+            })
+            .map(Self::read_loops)
+            .unwrap_or_default();
 
-        BlorbFile {
+        Ok(BlorbLoader {
             data,
+            index,
+            lookup,
+            loops,
+        })
+    }
+
+    fn read_index(
+        data: &[u8],
+        chunks: &HashMap<usize, ([u8; 4], Range<usize>)>,
+    ) -> Result<Vec<IndexEntry>> {
+        let read_u32 = |at: usize| -> Result<u32> {
+            data.get(at..at + 4)
+                .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+                .ok_or_else(|| invalid_file_error("Resource index ended unexpectedly."))
+        };
+
+        // Check the count against the chunk's size before trusting it to size the index.
+        let count = read_u32(0)? as usize;
+        if count > (data.len() - 4) / 12 {
+            return Err(invalid_file_error("Resource index ended unexpectedly."));
+        }
+        let mut index = Vec::with_capacity(count);
+        for i in 0..count {
+            let at = 4 + i * 12;
+            let usage = IndexKind::from_index_name(&read_u32(at)?.to_be_bytes())
+                .ok_or_else(|| invalid_file_error("Invalid resource usage."))?;
+            let number = read_u32(at + 4)?;
+            let start = read_u32(at + 8)? as usize;
+            let (id, range) = chunks
+                .get(&start)
+                .ok_or_else(|| invalid_file_error("Resource index points to a missing chunk."))?;
+            index.push(IndexEntry {
+                usage,
+                number,
+                kind: ChunkKind::new(usage, id),
+                range: range.clone(),
+            });
+        }
+        Ok(index)
+    }
+
+    fn read_loops(data: &[u8]) -> HashMap<u32, u32> {
+        data.chunks_exact(8)
+            .map(|entry| {
+                let number = u32::from_be_bytes(entry[0..4].try_into().unwrap());
+                let count = u32::from_be_bytes(entry[4..8].try_into().unwrap());
+                (number, count)
+            })
+            .collect()
+    }
+
+    fn chunk(&self, entry: &IndexEntry) -> Chunk<'_> {
+        Chunk {
+            number: entry.number,
+            kind: entry.kind.clone(),
+            data: &self.data[entry.range.clone()],
+        }
+    }
+
+    /// Iterate over every resource in the index.
+    pub fn chunks(&self) -> ChunkIter<'_> {
+        ChunkIter {
+            loader: self,
+            from: 0,
+        }
+    }
+
+    /// Find a resource by its usage and number.
+    pub fn resource(&self, usage: IndexKind, number: u32) -> Option<Chunk<'_>> {
+        self.lookup
+            .get(&(usage, number))
+            .map(|&i| self.chunk(&self.index[i]))
+    }
+
+    pub fn picture(&self, number: u32) -> Option<Chunk<'_>> {
+        self.resource(IndexKind::Picture, number)
+    }
+
+    pub fn sound(&self, number: u32) -> Option<Chunk<'_>> {
+        self.resource(IndexKind::Sound, number)
+    }
+
+    /// The number of times the `Loop` chunk says to play a sound (0 for forever), if it says.
+    pub fn sound_loop(&self, number: u32) -> Option<u32> {
+        self.loops.get(&number).copied()
+    }
+
+    pub fn data_resource(&self, number: u32) -> Option<Chunk<'_>> {
+        self.resource(IndexKind::Data, number)
+    }
+
+    /// Returns the story file, if there is one.
+    pub fn executable(&self) -> Option<Chunk<'_>> {
+        self.resource(IndexKind::Executable, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::iff::{DataChunk, FormChunk};
+
+    /// Build a Blorb file holding the given resources, each with its usage and number.
+    fn blorb(resources: Vec<(&[u8; 4], u32, iff::Chunk)>, extra: Vec<iff::Chunk>) -> Vec<u8> {
+        let index_size = 8 + 4 + 12 * resources.len() as u32;
+        let mut index = (resources.len() as u32).to_be_bytes().to_vec();
+        let mut offset = 12 + index_size;
+        for (usage, number, chunk) in &resources {
+            index.extend(*usage);
+            index.extend(number.to_be_bytes());
+            index.extend(offset.to_be_bytes());
+            offset += chunk.size();
         }
+        let mut chunks = vec![iff::Chunk::Data(DataChunk::new(*b"RIdx", index))];
+        chunks.extend(resources.into_iter().map(|(_, _, chunk)| chunk));
+        chunks.extend(extra);
+        iff::Chunk::Form(FormChunk::new(*b"IFRS", chunks)).to_bytes()
     }
 
+    fn data(kind: &[u8; 4], data: &[u8]) -> iff::Chunk {
+        iff::Chunk::Data(DataChunk::new(*kind, data.to_vec()))
+    }
+
+    #[test]
+    fn resources_are_found_by_usage_and_number() {
+        let sound = iff::Chunk::Form(FormChunk::new(*b"AIFF", vec![data(b"COMM", &[0; 18])]));
+        let file = blorb(
+            vec![
+                (b"Exec", 0, data(b"ZCOD", b"story")),
+                (b"Pict", 1, data(b"PNG ", b"odd")),
+                (b"Snd ", 3, sound.clone()),
+            ],
+            vec![data(b"Loop", &[0, 0, 0, 3, 0, 0, 0, 2])],
+        );
+        assert!(BlorbLoader::is_blorb(&file));
+        let loader = BlorbLoader::new(file).unwrap();
+
+        let executable = loader.executable().unwrap();
+        assert_eq!(
+            executable.kind,
+            ChunkKind::Executable {
+                system: ExecutableSystem::ZCode
+            }
+        );
+        assert_eq!(executable.data, b"story");
+
+        let picture = loader.picture(1).unwrap();
+        assert_eq!(
+            picture.kind,
+            ChunkKind::Picture {
+                format: PictureFormat::Png
+            }
+        );
+        // The padding byte isn't part of the picture.
+        assert_eq!(picture.data, b"odd");
+
+        // A sound stored as a form keeps its header.
+        let sound_chunk = loader.sound(3).unwrap();
+        assert_eq!(
+            sound_chunk.kind,
+            ChunkKind::Sound {
+                format: SoundFormat::Aiff
+            }
+        );
+        assert_eq!(sound_chunk.data, sound.to_bytes());
+        assert_eq!(loader.sound_loop(3), Some(2));
+        assert_eq!(loader.sound_loop(4), None);
+
+        assert!(loader.picture(3).is_none());
+        assert!(loader.sound(1).is_none());
+        let numbers: Vec<u32> = loader.chunks().map(|chunk| chunk.number).collect();
+        assert_eq!(numbers, [0, 1, 3]);
+    }
+
+    #[test]
+    fn index_pointing_between_chunks_is_rejected() {
+        let mut file = blorb(vec![(b"Pict", 1, data(b"PNG ", b"picture"))], vec![]);
+        // Move the entry's offset into the middle of the picture.
+        file[35] += 2;
+        assert!(BlorbLoader::new(file).is_err());
+    }
+
+    #[test]
+    fn truncated_index_is_rejected() {
+        let mut file = blorb(vec![(b"Pict", 1, data(b"PNG ", b"picture"))], vec![]);
+        // Claim a second entry that isn't there.
+        file[23] = 2;
+        assert!(BlorbLoader::new(file).is_err());
+    }
+
+    #[test]
+    fn huge_index_count_is_rejected() {
+        let mut file = blorb(vec![(b"Pict", 1, data(b"PNG ", b"picture"))], vec![]);
+        file[20..24].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(BlorbLoader::new(file).is_err());
+    }
+
+    #[test]
+    fn unknown_usage_is_rejected() {
+        let file = blorb(vec![(b"Misc", 1, data(b"PNG ", b"picture"))], vec![]);
+        assert!(BlorbLoader::new(file).is_err());
+    }
 
-    fn chunks(&'a self) -> ChunkIter {
+    #[test]
+    fn index_must_come_first() {
+        let file = iff::Chunk::Form(FormChunk::new(
+            *b"IFRS",
+            vec![data(b"PNG ", b"picture"), data(b"RIdx", &[0, 0, 0, 0])],
+        ))
+        .to_bytes();
+        assert!(BlorbLoader::new(file).is_err());
     }
 }
@@ -1,5 +1,3 @@
-use std::error::Error as StdError;
-use std::fmt::{self, Display};
 use std::io;
 use std::io::prelude::*;
 use std::io::SeekFrom;
@@ -21,20 +19,258 @@ pub struct FormChunk {
     chunks: Vec<Chunk>,
 }
 
+impl FormChunk {
+    pub fn new(kind: [u8; 4], chunks: Vec<Chunk>) -> Self {
+        Self { kind, chunks }
+    }
+
+    /// The form type (e.g. `IFZS`).
+    pub fn kind(&self) -> &[u8; 4] {
+        &self.kind
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /// Find the first data chunk with the given ID.
+    pub fn data_chunk(&self, kind: &[u8; 4]) -> Option<&DataChunk> {
+        self.chunks.iter().find_map(|chunk| match chunk {
+            Chunk::Data(data) if &data.kind == kind => Some(data),
+            _ => None,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DataChunk {
     kind: [u8; 4],
     data: Vec<u8>,
 }
 
-// TODO: Add LIST and CAT.
-#[non_exhaustive]
+impl DataChunk {
+    pub fn new(kind: [u8; 4], data: Vec<u8>) -> Self {
+        Self { kind, data }
+    }
+
+    /// The chunk ID (e.g. `IFhd`).
+    pub fn kind(&self) -> &[u8; 4] {
+        &self.kind
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// A `LIST`, which groups forms together with shared properties.
+#[derive(Debug, Clone)]
+pub struct ListChunk {
+    kind: [u8; 4],
+    props: Vec<PropChunk>,
+    chunks: Vec<Chunk>,
+}
+
+impl ListChunk {
+    pub fn new(kind: [u8; 4], props: Vec<PropChunk>, chunks: Vec<Chunk>) -> Self {
+        Self {
+            kind,
+            props,
+            chunks,
+        }
+    }
+
+    /// The type of the list's contents, or four spaces if the contents are mixed.
+    pub fn kind(&self) -> &[u8; 4] {
+        &self.kind
+    }
+
+    pub fn props(&self) -> &[PropChunk] {
+        &self.props
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+}
+
+/// A `CAT `, which concatenates forms, lists and other concatenations.
+#[derive(Debug, Clone)]
+pub struct CatChunk {
+    kind: [u8; 4],
+    chunks: Vec<Chunk>,
+}
+
+impl CatChunk {
+    pub fn new(kind: [u8; 4], chunks: Vec<Chunk>) -> Self {
+        Self { kind, chunks }
+    }
+
+    /// The type of the concatenation's contents, or four spaces if the contents are mixed.
+    pub fn kind(&self) -> &[u8; 4] {
+        &self.kind
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+}
+
+/// A `PROP`, which holds properties shared by every form of the given type in a list.
+#[derive(Debug, Clone)]
+pub struct PropChunk {
+    kind: [u8; 4],
+    chunks: Vec<DataChunk>,
+}
+
+impl PropChunk {
+    pub fn new(kind: [u8; 4], chunks: Vec<DataChunk>) -> Self {
+        Self { kind, chunks }
+    }
+
+    /// The form type the properties apply to.
+    pub fn kind(&self) -> &[u8; 4] {
+        &self.kind
+    }
+
+    pub fn chunks(&self) -> &[DataChunk] {
+        &self.chunks
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Chunk {
     Form(FormChunk),
+    List(ListChunk),
+    Cat(CatChunk),
     Data(DataChunk),
 }
 
+impl Chunk {
+    /// The chunk's ID as it appears in the file (e.g. `FORM` or `IFhd`).
+    pub fn id(&self) -> &[u8; 4] {
+        match self {
+            Chunk::Form(_) => b"FORM",
+            Chunk::List(_) => b"LIST",
+            Chunk::Cat(_) => b"CAT ",
+            Chunk::Data(data) => &data.kind,
+        }
+    }
+
+    /// The chunk's type: the content type for group chunks, or the ID for data chunks.
+    pub fn kind(&self) -> &[u8; 4] {
+        match self {
+            Chunk::Form(form) => &form.kind,
+            Chunk::List(list) => &list.kind,
+            Chunk::Cat(cat) => &cat.kind,
+            Chunk::Data(data) => &data.kind,
+        }
+    }
+
+    /// The length of the chunk's content, excluding its 8-byte header and any padding.
+    pub fn content_length(&self) -> u32 {
+        match self {
+            Chunk::Form(form) => 4 + form.chunks.iter().map(Chunk::size).sum::<u32>(),
+            Chunk::List(list) => {
+                4 + list.props.iter().map(PropChunk::size).sum::<u32>()
+                    + list.chunks.iter().map(Chunk::size).sum::<u32>()
+            }
+            Chunk::Cat(cat) => 4 + cat.chunks.iter().map(Chunk::size).sum::<u32>(),
+            Chunk::Data(data) => data.data.len() as u32,
+        }
+    }
+
+    /// The total length of the serialized chunk, including its header and padding.
+    pub fn size(&self) -> u32 {
+        8 + padded(self.content_length())
+    }
+
+    /// Serialize the chunk, including its header and padding byte.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let len = self.content_length();
+        writer.write_all(self.id())?;
+        writer.write_all(&len.to_be_bytes())?;
+        match self {
+            Chunk::Form(form) => {
+                writer.write_all(&form.kind)?;
+                for chunk in &form.chunks {
+                    chunk.write(writer)?;
+                }
+            }
+            Chunk::List(list) => {
+                writer.write_all(&list.kind)?;
+                for prop in &list.props {
+                    prop.write(writer)?;
+                }
+                for chunk in &list.chunks {
+                    chunk.write(writer)?;
+                }
+            }
+            Chunk::Cat(cat) => {
+                writer.write_all(&cat.kind)?;
+                for chunk in &cat.chunks {
+                    chunk.write(writer)?;
+                }
+            }
+            Chunk::Data(data) => {
+                writer.write_all(&data.data)?;
+            }
+        }
+        if len % 2 == 1 {
+            writer.write_all(&[0])?;
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(self.size() as usize);
+        // Writing to a Vec can't fail.
+        self.write(&mut result).unwrap();
+        result
+    }
+}
+
+impl DataChunk {
+    fn size(&self) -> u32 {
+        8 + padded(self.data.len() as u32)
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.kind)?;
+        writer.write_all(&(self.data.len() as u32).to_be_bytes())?;
+        writer.write_all(&self.data)?;
+        if self.data.len() % 2 == 1 {
+            writer.write_all(&[0])?;
+        }
+        Ok(())
+    }
+}
+
+impl PropChunk {
+    fn content_length(&self) -> u32 {
+        4 + self.chunks.iter().map(DataChunk::size).sum::<u32>()
+    }
+
+    fn size(&self) -> u32 {
+        8 + self.content_length()
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b"PROP")?;
+        writer.write_all(&self.content_length().to_be_bytes())?;
+        writer.write_all(&self.kind)?;
+        for chunk in &self.chunks {
+            chunk.write(writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Round a chunk length up to the next even number.
+fn padded(len: u32) -> u32 {
+    len + len % 2
+}
+
 pub struct IffReader<F: Read + Seek> {
     reader: F,
 }
@@ -44,42 +280,139 @@ impl<F: Read + Seek> IffReader<F> {
         Self { reader }
     }
 
-    fn read_chunk(&mut self) -> Result<Chunk> {
-        let mut word = [0u8; 4];
-        let mut len = [0u8; 4];
+    /// Read a four-character ID.
+    fn read_id(&mut self) -> Result<[u8; 4]> {
+        let mut id = [0u8; 4];
+        self.reader.read_exact(&mut id)?;
+        Ok(id)
+    }
 
-        self.reader.read_exact(&mut word)?;
+    /// Read a chunk header, returning the chunk ID and content length. The length is checked
+    /// against what's left of the file, so a corrupt one can't make the content too big to
+    /// read.
+    fn read_header(&mut self) -> Result<([u8; 4], u32)> {
+        let id = self.read_id()?;
+        let mut len = [0u8; 4];
         self.reader.read_exact(&mut len)?;
         let len = u32::from_be_bytes(len);
 
-        let result = match &word {
+        let position = self.reader.stream_position()?;
+        let end = self.reader.seek(SeekFrom::End(0))?;
+        self.reader.seek(SeekFrom::Start(position))?;
+        if len as u64 > end - position {
+            return Err(IffReadError::FormatError(format!(
+                "\"{}\" chunk runs past the end of the file",
+                String::from_utf8_lossy(&id)
+            )));
+        }
+        Ok((id, len))
+    }
+
+    fn read_chunk(&mut self) -> Result<Chunk> {
+        let (id, len) = self.read_header()?;
+        self.read_body(id, len)
+    }
+
+    /// Read the content of a chunk whose header has already been read.
+    fn read_body(&mut self, id: [u8; 4], len: u32) -> Result<Chunk> {
+        let end = self.reader.stream_position()? + len as u64;
+        let chunk = match &id {
             b"FORM" => {
-                let mut kind = [0u8; 4];
-                self.reader.read_exact(&mut kind)?;
+                let kind = self.read_id()?;
                 let mut chunks = Vec::new();
-                while self.reader.stream_position()? < len as u64 {
+                while self.reader.stream_position()? < end {
                     chunks.push(self.read_chunk()?);
                 }
-                Ok(Chunk::Form(FormChunk { kind, chunks }))
+                Chunk::Form(FormChunk { kind, chunks })
             }
-            b"LIST" | b"CAT " => {
-                todo!();
+            b"LIST" => {
+                let kind = self.read_id()?;
+                let mut props = Vec::new();
+                let mut chunks = Vec::new();
+                while self.reader.stream_position()? < end {
+                    let (id, len) = self.read_header()?;
+                    if &id == b"PROP" {
+                        if !chunks.is_empty() {
+                            return Err(IffReadError::FormatError(
+                                "PROP chunks must come first in a LIST".into(),
+                            ));
+                        }
+                        props.push(self.read_prop(len)?);
+                    } else {
+                        chunks.push(self.read_group(id, len)?);
+                    }
+                }
+                Chunk::List(ListChunk {
+                    kind,
+                    props,
+                    chunks,
+                })
             }
-            _ => {
-                if len < 4 {
-                    return Err(IffReadError::FormatError("Invalid length specifier".into()));
+            b"CAT " => {
+                let kind = self.read_id()?;
+                let mut chunks = Vec::new();
+                while self.reader.stream_position()? < end {
+                    let (id, len) = self.read_header()?;
+                    chunks.push(self.read_group(id, len)?);
                 }
-                let mut data = vec![0u8; len as usize - 4];
-                let mut kind = [0u8; 4];
-                self.reader.read_exact(&mut kind)?;
+                Chunk::Cat(CatChunk { kind, chunks })
+            }
+            b"PROP" => {
+                return Err(IffReadError::FormatError(
+                    "PROP chunk found outside of a LIST".into(),
+                ));
+            }
+            _ => {
+                let mut data = vec![0u8; len as usize];
                 self.reader.read_exact(&mut data)?;
-                Ok(Chunk::Data(DataChunk { kind, data }))
+                Chunk::Data(DataChunk { kind: id, data })
             }
         };
-        if self.reader.stream_position()? % 2 == 1 {
+        self.finish_chunk(end)?;
+        Ok(chunk)
+    }
+
+    /// Read a member of a LIST or CAT, which must itself be a FORM, LIST or CAT.
+    fn read_group(&mut self, id: [u8; 4], len: u32) -> Result<Chunk> {
+        if !matches!(&id, b"FORM" | b"LIST" | b"CAT ") {
+            return Err(IffReadError::FormatError(format!(
+                "Unexpected \"{}\" chunk in a LIST or CAT",
+                String::from_utf8_lossy(&id)
+            )));
+        }
+        self.read_body(id, len)
+    }
+
+    fn read_prop(&mut self, len: u32) -> Result<PropChunk> {
+        let end = self.reader.stream_position()? + len as u64;
+        let kind = self.read_id()?;
+        let mut chunks = Vec::new();
+        while self.reader.stream_position()? < end {
+            match self.read_chunk()? {
+                Chunk::Data(data) => chunks.push(data),
+                _ => {
+                    return Err(IffReadError::FormatError(
+                        "PROP chunks can only contain data chunks".into(),
+                    ))
+                }
+            }
+        }
+        self.finish_chunk(end)?;
+        Ok(PropChunk { kind, chunks })
+    }
+
+    /// Check the chunk's content ended where its length said it would, then skip the padding
+    /// byte, if there is one.
+    fn finish_chunk(&mut self, end: u64) -> Result<()> {
+        if self.reader.stream_position()? != end {
+            return Err(IffReadError::FormatError(
+                "Chunk content overruns its length".into(),
+            ));
+        }
+        if end % 2 == 1 {
             self.reader.seek(SeekFrom::Current(1))?;
         }
-        result
+        Ok(())
     }
 
     pub fn load(&mut self) -> Result<Chunk> {
@@ -103,3 +436,145 @@ impl<F: Read + Seek> IffReader<F> {
         Ok(chunk)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn data(kind: &[u8; 4], data: &[u8]) -> DataChunk {
+        DataChunk::new(*kind, data.to_vec())
+    }
+
+    fn form(kind: &[u8; 4], chunks: Vec<DataChunk>) -> Chunk {
+        Chunk::Form(FormChunk::new(
+            *kind,
+            chunks.into_iter().map(Chunk::Data).collect(),
+        ))
+    }
+
+    fn load(bytes: &[u8]) -> Result<Chunk> {
+        IffReader::new(Cursor::new(bytes)).load()
+    }
+
+    #[test]
+    fn odd_length_chunks_are_padded() {
+        let chunk = form(b"TEST", vec![data(b"ODD ", b"abc"), data(b"EVEN", b"de")]);
+        let bytes = chunk.to_bytes();
+        assert_eq!(bytes.len() as u32, chunk.size());
+        // The form's length counts the padding inside it.
+        assert_eq!(&bytes[4..8], &[0, 0, 0, 26]);
+        assert_eq!(&bytes[20..24], b"abc\0");
+        assert_eq!(&bytes[24..28], b"EVEN");
+
+        let form = match load(&bytes).unwrap() {
+            Chunk::Form(form) => form,
+            chunk => panic!("Expected a FORM, got {:?}", chunk),
+        };
+        assert_eq!(form.data_chunk(b"ODD ").unwrap().data(), b"abc");
+        assert_eq!(form.data_chunk(b"EVEN").unwrap().data(), b"de");
+    }
+
+    #[test]
+    fn list_with_properties() {
+        let list = Chunk::List(ListChunk::new(
+            *b"TEST",
+            vec![PropChunk::new(*b"TEST", vec![data(b"NAME", b"shared")])],
+            vec![
+                form(b"TEST", vec![data(b"BODY", b"x")]),
+                form(b"TEST", vec![]),
+            ],
+        ));
+        let bytes = list.to_bytes();
+        assert_eq!(bytes.len() as u32, list.size());
+
+        let list = match load(&bytes).unwrap() {
+            Chunk::List(list) => list,
+            chunk => panic!("Expected a LIST, got {:?}", chunk),
+        };
+        assert_eq!(list.kind(), b"TEST");
+        assert_eq!(list.props().len(), 1);
+        assert_eq!(list.props()[0].kind(), b"TEST");
+        assert_eq!(list.props()[0].chunks()[0].data(), b"shared");
+        assert_eq!(list.chunks().len(), 2);
+        assert_eq!(list.chunks()[0].id(), b"FORM");
+        assert_eq!(list.chunks()[1].content_length(), 4);
+    }
+
+    #[test]
+    fn nested_concatenation() {
+        let cat = Chunk::Cat(CatChunk::new(
+            *b"    ",
+            vec![
+                form(b"ONE ", vec![data(b"DATA", b"1")]),
+                Chunk::Cat(CatChunk::new(*b"TWO ", vec![form(b"TWO ", vec![])])),
+            ],
+        ));
+        let bytes = cat.to_bytes();
+
+        let cat = match load(&bytes).unwrap() {
+            Chunk::Cat(cat) => cat,
+            chunk => panic!("Expected a CAT, got {:?}", chunk),
+        };
+        assert_eq!(cat.kind(), b"    ");
+        assert_eq!(cat.chunks()[0].kind(), b"ONE ");
+        match &cat.chunks()[1] {
+            Chunk::Cat(inner) => assert_eq!(inner.chunks()[0].kind(), b"TWO "),
+            chunk => panic!("Expected a CAT, got {:?}", chunk),
+        }
+    }
+
+    #[test]
+    fn property_after_form_is_rejected() {
+        let mut bytes = b"LIST\0\0\0\x1CTEST".to_vec();
+        bytes.extend(form(b"TEST", vec![]).to_bytes());
+        bytes.extend(b"PROP\0\0\0\x04TEST");
+        assert!(matches!(
+            load(&bytes),
+            Err(IffReadError::FormatError(message)) if message.contains("must come first")
+        ));
+    }
+
+    #[test]
+    fn property_outside_list_is_rejected() {
+        assert!(load(b"FORM\0\0\0\x10TESTPROP\0\0\0\x04TEST").is_err());
+    }
+
+    #[test]
+    fn data_chunk_in_concatenation_is_rejected() {
+        assert!(matches!(
+            load(b"CAT \0\0\0\x0C    DATA\0\0\0\0"),
+            Err(IffReadError::FormatError(message)) if message.contains("\"DATA\"")
+        ));
+    }
+
+    #[test]
+    fn chunk_overrunning_its_group_is_rejected() {
+        // The form claims 8 bytes, but its data chunk needs 12.
+        assert!(matches!(
+            load(b"FORM\0\0\0\x08TESTDATA\0\0\0\x04abcd"),
+            Err(IffReadError::FormatError(message)) if message.contains("overruns")
+        ));
+    }
+
+    #[test]
+    fn truncated_chunk_is_rejected() {
+        // The data chunk claims nearly 4GB, but only four bytes follow it.
+        assert!(matches!(
+            load(b"FORM\0\0\0\x10TESTDATA\xFF\xFF\xFF\xF0abcd"),
+            Err(IffReadError::FormatError(message)) if message.contains("past the end")
+        ));
+        let mut bytes = form(b"TEST", vec![data(b"DATA", b"abcd")]).to_bytes();
+        bytes.truncate(bytes.len() - 2);
+        assert!(load(&bytes).is_err());
+    }
+
+    #[test]
+    fn trailing_data_is_rejected() {
+        let mut bytes = form(b"TEST", vec![]).to_bytes();
+        bytes.extend(b"junk");
+        assert!(load(&bytes).is_err());
+        assert!(load(b"JUNK\0\0\0\0").is_err());
+    }
+}